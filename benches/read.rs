@@ -0,0 +1,118 @@
+//! Compare reading every file of a directory of bundles through
+//! `ChunkReader` and through memory mapped files.
+//!
+//! ```
+//! LIMN_BENCH_DIR=<bundle dir> cargo bench --features mmap
+//! ```
+//!
+//! Oodle is loaded from `LIMN_OODLE` or the library search path. With the
+//! `kraken` feature the built in decoder is used when it can't be loaded.
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+
+use limn::bundle_hash_from;
+use limn::mmap::MappedBundle;
+use limn::oodle::Oodle;
+use limn::oodle::LIBRARY_NAMES;
+use limn::read::ChunkReader;
+use limn::BundleFd;
+use limn::Decompressor;
+
+// reads every bundle returning the decompressed size
+type Bench = fn(&[(PathBuf, u64)], &dyn Decompressor) -> u64;
+
+// read all files of a bundle returning their decompressed size
+fn read_files(bundle: &mut BundleFd<'_>, oodle: &dyn Decompressor, scratch: &mut Vec<u8>) -> u64 {
+    let mut size = 0;
+    let mut files = bundle.files(oodle, scratch).unwrap();
+    while let Some(mut entry) = files.next_file().unwrap() {
+        size += io::copy(&mut entry, &mut io::sink()).unwrap();
+    }
+    size
+}
+
+fn chunk_reader(bundles: &[(PathBuf, u64)], oodle: &dyn Decompressor) -> u64 {
+    let mut buf = vec![0; 0x80000];
+    let mut scratch = Vec::new();
+    let mut size = 0;
+    for (path, hash) in bundles {
+        let mut rdr = ChunkReader::new(&mut buf, File::open(path).unwrap());
+        let mut bundle = BundleFd::new(Some(*hash), &mut rdr).unwrap();
+        size += read_files(&mut bundle, oodle, &mut scratch);
+    }
+    size
+}
+
+fn mmap(bundles: &[(PathBuf, u64)], oodle: &dyn Decompressor) -> u64 {
+    let mut scratch = Vec::new();
+    let mut size = 0;
+    for (path, hash) in bundles {
+        let map = MappedBundle::map(&File::open(path).unwrap()).unwrap();
+        let mut rdr = Cursor::new(map.bytes());
+        let mut bundle = BundleFd::from_bytes(Some(*hash), &mut rdr).unwrap();
+        size += read_files(&mut bundle, oodle, &mut scratch);
+    }
+    size
+}
+
+fn main() {
+    let Some(dir) = env::var_os("LIMN_BENCH_DIR") else {
+        eprintln!("set LIMN_BENCH_DIR to a directory of bundles");
+        return;
+    };
+
+    let paths = env::var_os("LIMN_OODLE")
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(LIBRARY_NAMES.iter().map(PathBuf::from));
+    let oodle: Box<dyn Decompressor> = match Oodle::load_first(paths) {
+        Ok(oodle) => Box::new(oodle),
+        #[cfg(feature = "kraken")]
+        Err(_) => Box::new(limn::Kraken),
+        #[cfg(not(feature = "kraken"))]
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+
+    let mut bundles = Vec::new();
+    for fd in fs::read_dir(dir).unwrap() {
+        let path = fd.unwrap().path();
+        if path.extension().is_none() {
+            if let Some(hash) = bundle_hash_from(&path) {
+                bundles.push((path, hash));
+            }
+        }
+    }
+    bundles.sort();
+    let iterations = env::var("LIMN_BENCH_ITERATIONS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(3);
+
+    // first pass warms the page cache
+    let size = chunk_reader(&bundles, &*oodle);
+    println!("{} bundles, {} MiB decompressed", bundles.len(), size >> 20);
+
+    let benches: [(&str, Bench); 2] = [
+        ("ChunkReader", chunk_reader),
+        ("mmap", mmap),
+    ];
+    for (name, bench) in benches {
+        let mut best = Duration::MAX;
+        for _ in 0..iterations {
+            let start = Instant::now();
+            assert_eq!(size, bench(&bundles, &*oodle));
+            best = best.min(start.elapsed());
+        }
+        let mib_s = size as f64 / (1 << 20) as f64 / best.as_secs_f64();
+        println!("{name:>12}: {:>8.3}s {mib_s:>10.1} MiB/s", best.as_secs_f64());
+    }
+}
@@ -1,11 +1,19 @@
+use std::collections::VecDeque;
 use std::io;
+use std::io::Cursor;
 use std::io::Read;
 use std::io::Seek;
 use std::io::SeekFrom;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread;
 use byteorder::ReadBytesExt;
 use byteorder::LE;
 
-use crate::oodle;
+use crate::decompress::Decompressor;
+use crate::error::Error;
+use crate::error::Result;
 
 pub trait ReadSeek: Read + Seek {}
 impl<T: Read + Seek> ReadSeek for T {}
@@ -13,13 +21,19 @@ impl<T: Read + Seek> ReadSeek for T {}
 pub struct BundleFd<'a> {
     rdr: &'a mut dyn ReadSeek,
     pub name: Option<u64>,
+    /// Bundle format version, 7 or 8.
+    pub version: u8,
     pub num_files: u32,
+    offsets: Option<Vec<EntryOffset>>,
+    // whole bundle when it is in memory
+    bytes: Option<&'a [u8]>,
+    prefetch: Option<(&'a ChunkPool, usize)>,
 }
 
 impl<'a> BundleFd<'a> {
-    pub fn new(name: Option<u64>, rdr: &'a mut dyn ReadSeek) -> io::Result<Self> {
+    pub fn new(name: Option<u64>, rdr: &'a mut dyn ReadSeek) -> Result<Self> {
         let mut header = [0_u8; 8];
-        rdr.read_exact(&mut header)?;
+        rdr.read_exact(&mut header).map_err(|e| Error::from_io(name, 0, e))?;
         if header != [
             // bundle version
             0x08, 0x00,
@@ -37,50 +51,181 @@ impl<'a> BundleFd<'a> {
             0x03, 0x00,
             0x00, 0x00,
         ] {
-            panic!("unknown bundle version {}", header[0]);
+            return Err(Error::UnknownVersion {
+                bundle: name,
+                offset: 0,
+                header,
+            });
         }
 
-        let num_files = rdr.read_u32::<LE>()?;
+        let num_files = read_u32(rdr, name)?;
 
         Ok(Self {
             rdr,
             name,
+            version: header[0],
             num_files,
+            offsets: None,
+            bytes: None,
+            prefetch: None,
         })
     }
 
-    pub fn index(&mut self) -> IndexIter<'_> {
-        IndexIter::new(self.rdr, self.num_files)
+    /// Keep up to `chunks` chunks decompressing on `pool` ahead of the read
+    /// position while reading files. Chunks are decompressed even if the
+    /// files in them are skipped.
+    ///
+    /// Only worth it for large bundles when other threads are idle. `0` or
+    /// `1` decompresses one chunk at a time on the reading thread.
+    pub fn set_prefetch(&mut self, pool: &'a ChunkPool, chunks: usize) {
+        self.prefetch = Some((pool, chunks));
+    }
+
+    /// Open a bundle that is already in memory such as a memory mapped file.
+    ///
+    /// Compressed chunks are passed to the decompressor straight from the
+    /// slice and stored chunks are read without copying them first.
+    pub fn from_bytes<'s: 'a>(name: Option<u64>, rdr: &'a mut Cursor<&'s [u8]>) -> Result<Self> {
+        let bytes = *rdr.get_ref();
+        let mut fd = Self::new(name, rdr)?;
+        fd.bytes = Some(bytes);
+        Ok(fd)
+    }
+
+    /// The 256 byte block after the header that is not used by limn.
+    pub fn reserved(&mut self) -> Result<[u8; 256]> {
+        let mut reserved = [0; 256];
+        self.rdr.seek(SeekFrom::Start(12)).map_err(|e| Error::from_io(self.name, 12, e))?;
+        self.rdr.read_exact(&mut reserved).map_err(|e| Error::from_io(self.name, 12, e))?;
+        Ok(reserved)
+    }
+
+    /// Read the chunk table without decompressing anything.
+    pub fn chunk_table(&mut self) -> Result<ChunkTable> {
+        ChunkTable::read(self.rdr, self.name, self.num_files)
+    }
+
+    /// Iterate over the index. Fails if the reader can not seek back to the
+    /// index such as a stream that was already read past it.
+    pub fn index(&mut self) -> Result<IndexIter<'_>> {
+        IndexIter::new(self.rdr, self.name, self.num_files)
+    }
+
+    /// Offsets of entries if they were computed by [`FilesIter::entry_offsets`].
+    pub fn entry_offsets(&self) -> Option<&[EntryOffset]> {
+        self.offsets.as_deref()
     }
 
-    fn reader<'b>(&'b mut self, oodle: &'b oodle::Oodle, scratch: &'b mut Vec<u8>) -> OodleRead<'b> {
-        let needed = oodle.memory_size_needed().unwrap() as usize;
+    pub fn files<'b>(&'b mut self, oodle: &'b dyn Decompressor, scratch: &'b mut Vec<u8>) -> Result<FilesIter<'b>> {
+        let needed = oodle.memory_size_needed()
+            .map_err(|e| Error::from_io(self.name, 0, e))? as usize;
         if scratch.len() < CHUNK_SIZE * 2 + needed {
             scratch.resize(CHUNK_SIZE * 2 + needed, 0);
         }
         let (in_buf, scratch) = scratch.split_at_mut(CHUNK_SIZE);
         let (out_buf, scratch) = scratch.split_at_mut(CHUNK_SIZE);
         let (scratch, _) = scratch.split_at_mut(needed);
-        match OodleRead::new(
+        let mut oodle = OodleRead::new(
             oodle,
             self.rdr,
+            self.name,
             self.num_files,
             <&mut [u8; CHUNK_SIZE]>::try_from(in_buf).unwrap(),
             <&mut [u8; CHUNK_SIZE]>::try_from(out_buf).unwrap(),
             scratch,
-        ) {
-            Ok(o) => o,
-            Err(e) => if let Some(bundle_hash) = self.name {
-                panic!("{bundle_hash:016x} {e}");
-            } else {
-                panic!("anonymous bundle {e}");
+        )?;
+        oodle.bytes = self.bytes;
+        if let Some((pool, chunks)) = self.prefetch {
+            oodle.prefetch.pool = Some(pool);
+            oodle.prefetch.chunks = chunks;
+        }
+        Ok(FilesIter::new(oodle, &mut self.offsets, self.num_files))
+    }
+
+    /// Check the whole bundle without extracting anything.
+    ///
+    /// On top of the checks done while reading files this compares the size
+    /// prefix of every chunk with the chunk table, requires the alignment
+    /// padding to be zero and the index to match the file entries. Every
+    /// chunk is decompressed.
+    pub fn verify(&mut self, oodle: &dyn Decompressor, scratch: &mut Vec<u8>) -> Result<()> {
+        let name = self.name;
+        let index = self.index()?.collect::<Vec<_>>();
+        if index.len() != self.num_files as usize {
+            return Err(Error::Truncated {
+                bundle: name,
+                offset: 12 + 256 + index.len() as u64 * 20,
+            });
+        }
+
+        let table = self.chunk_table()?;
+        let sizes_end = 12 + 256 + u64::from(self.num_files) * 20 + 4 + table.len() as u64 * 4;
+        check_padding(self.rdr, name, sizes_end)?;
+
+        let mut end = table.offsets.first().copied().unwrap_or(sizes_end + align_16(sizes_end) as u64 + 8);
+        for (i, (&size, &offset)) in table.sizes.iter().zip(&table.offsets).enumerate() {
+            self.rdr.seek(SeekFrom::Start(offset)).map_err(|e| Error::from_io(name, offset, e))?;
+            let prefix = read_u32(self.rdr, name)?;
+            if prefix != size {
+                return Err(Error::SizeMismatch {
+                    bundle: name,
+                    offset,
+                    field: "chunk size",
+                    expected: size.into(),
+                    found: prefix.into(),
+                });
+            }
+            check_padding(self.rdr, name, offset + 4)?;
+            end = table.data_offset(i) + u64::from(size);
+        }
+
+        let len = self.rdr.seek(SeekFrom::End(0)).map_err(|e| Error::from_io(name, end, e))?;
+        if len != end {
+            return Err(Error::SizeMismatch {
+                bundle: name,
+                offset: end.min(len),
+                field: "bundle size",
+                expected: end,
+                found: len,
+            });
+        }
+
+        let mut files = self.files(oodle, scratch)?;
+        let mut index = index.into_iter();
+        while let Some(mut entry) = files.next_file()? {
+            let expected = index.next().unwrap();
+            if expected.ext != entry.ext || expected.name != entry.name {
+                let offset = entry.rdr.position;
+                return Err(Error::UnexpectedValue {
+                    bundle: name,
+                    offset,
+                    field: "entry name",
+                    value: entry.name,
+                });
             }
+            io::copy(&mut entry, &mut io::sink()).map_err(|e| Error::from_io(name, 0, e))?;
         }
+
+        Ok(())
     }
 
-    pub fn files<'b>(&'b mut self, oodle: &'b oodle::Oodle, scratch: &'b mut Vec<u8>) -> FilesIter<'_> {
-        let num_files = self.num_files;
-        FilesIter::new(self.reader(oodle, scratch), num_files)
+    /// Get the files of the bundle positioned at the entry with `ext` and
+    /// `name`.
+    ///
+    /// Returns `None` if the bundle has no such entry.
+    pub fn seek_to_entry<'b>(
+        &'b mut self,
+        oodle: &'b dyn Decompressor,
+        scratch: &'b mut Vec<u8>,
+        ext: u64,
+        name: u64,
+    ) -> Result<Option<FilesIter<'b>>> {
+        let mut files = self.files(oodle, scratch)?;
+        if files.seek_to_entry(ext, name)? {
+            Ok(Some(files))
+        } else {
+            Ok(None)
+        }
     }
 }
 
@@ -91,14 +236,14 @@ pub struct IndexIter<'a> {
 }
 
 impl<'a> IndexIter<'a> {
-    fn new(rdr: &'a mut dyn ReadSeek, num_files: u32) -> Self {
-        rdr.seek(SeekFrom::Start(12 + 256)).unwrap();
+    fn new(rdr: &'a mut dyn ReadSeek, bundle: Option<u64>, num_files: u32) -> Result<Self> {
+        rdr.seek(SeekFrom::Start(12 + 256)).map_err(|e| Error::from_io(bundle, 12 + 256, e))?;
 
-        Self {
+        Ok(Self {
             rdr,
             num_files,
             offset: 0,
-        }
+        })
     }
 }
 
@@ -127,7 +272,30 @@ pub struct IndexEntry {
     pub mode: u32,
 }
 
-fn align_16(offset: u64) -> i64 {
+// read u32 from the bundle file and report errors at the current offset
+fn read_u32(rdr: &mut dyn ReadSeek, bundle: Option<u64>) -> Result<u32> {
+    let offset = rdr.stream_position().map_err(|e| Error::from_io(bundle, 0, e))?;
+    rdr.read_u32::<LE>().map_err(|e| Error::from_io(bundle, offset, e))
+}
+
+// padding from `offset` to the next multiple of 16 must be zero
+fn check_padding(rdr: &mut dyn ReadSeek, bundle: Option<u64>, offset: u64) -> Result<()> {
+    let mut padding = [0; 16];
+    let padding = &mut padding[..align_16(offset) as usize];
+    rdr.seek(SeekFrom::Start(offset)).map_err(|e| Error::from_io(bundle, offset, e))?;
+    rdr.read_exact(padding).map_err(|e| Error::from_io(bundle, offset, e))?;
+    if let Some(i) = padding.iter().position(|&b| b != 0) {
+        return Err(Error::UnexpectedValue {
+            bundle,
+            offset: offset + i as u64,
+            field: "padding",
+            value: padding[i].into(),
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn align_16(offset: u64) -> i64 {
     let offset = (offset % 16) as i64;
     if offset != 0 {
         16 - offset
@@ -136,201 +304,535 @@ fn align_16(offset: u64) -> i64 {
     }
 }
 
-const CHUNK_SIZE: usize = 0x80000;
+pub(crate) const CHUNK_SIZE: usize = 0x80000;
+
+/// Table of the compressed chunks in a bundle.
+///
+/// Every chunk decompresses to `CHUNK_SIZE` bytes so the chunk holding any
+/// offset of the decompressed data can be found without decompressing the
+/// chunks before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkTable {
+    sizes: Vec<u32>,
+    // file offset of the size prefix of each chunk
+    offsets: Vec<u64>,
+    total_size: u64,
+}
+
+impl ChunkTable {
+    fn read(rdr: &mut dyn ReadSeek, bundle: Option<u64>, num_files: u32) -> Result<Self> {
+        let table = 12 + 256 + u64::from(num_files) * 20;
+        rdr.seek(SeekFrom::Start(table)).map_err(|e| Error::from_io(bundle, table, e))?;
+        let num_chunks = read_u32(rdr, bundle)?;
+        let mut sizes = Vec::with_capacity(num_chunks as usize);
+        for _ in 0..num_chunks {
+            sizes.push(read_u32(rdr, bundle)?);
+        }
+
+        let position = rdr.stream_position().map_err(|e| Error::from_io(bundle, table, e))?;
+        let padding = align_16(position);
+        if padding > 0 {
+            rdr.seek(SeekFrom::Current(padding)).map_err(|e| Error::from_io(bundle, position, e))?;
+        }
+        let total_size = read_u32(rdr, bundle)? as u64;
+        let zero = read_u32(rdr, bundle)?;
+
+        if 0 != zero {
+            return Err(Error::UnexpectedValue {
+                bundle,
+                offset: position + padding as u64 + 4,
+                field: "zero after total size",
+                value: zero.into(),
+            });
+        }
+
+        if total_size > u64::from(num_chunks) * CHUNK_SIZE as u64 {
+            return Err(Error::SizeMismatch {
+                bundle,
+                offset: position + padding as u64,
+                field: "total size",
+                expected: u64::from(num_chunks) * CHUNK_SIZE as u64,
+                found: total_size,
+            });
+        }
+
+        let mut offsets = Vec::with_capacity(num_chunks as usize);
+        let mut offset = position + padding as u64 + 8;
+        for &size in &sizes {
+            offsets.push(offset);
+            offset += 4;
+            offset += align_16(offset) as u64 + u64::from(size);
+        }
+
+        Ok(Self {
+            sizes,
+            offsets,
+            total_size,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.sizes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sizes.is_empty()
+    }
+
+    /// Compressed size of every chunk.
+    pub fn sizes(&self) -> &[u32] {
+        &self.sizes
+    }
+
+    /// File offset of the compressed data of `chunk`.
+    pub fn data_offset(&self, chunk: usize) -> u64 {
+        let offset = self.offsets[chunk] + 4;
+        offset + align_16(offset) as u64
+    }
+
+    /// Size of all files after decompression.
+    pub fn total_size(&self) -> u64 {
+        self.total_size
+    }
+}
 
 struct OodleRead<'a> {
-    oodle: &'a oodle::Oodle,
+    oodle: &'a dyn Decompressor,
     rdr: &'a mut dyn ReadSeek,
+    bundle: Option<u64>,
     in_buf: &'a mut [u8; CHUNK_SIZE],
     out_buf: &'a mut [u8; CHUNK_SIZE],
     scratch: &'a mut [u8],
-    offset: usize,
-    total_out: usize,
-    total_size: u64,
-    num_chunks: u32,
-    current: u32,
+    table: ChunkTable,
+    // chunk that is decompressed in out_buf
+    loaded: Option<u32>,
+    // chunk that rdr is positioned at
+    next_chunk: u32,
+    // offset in decompressed data
+    position: u64,
+    // chunks are read from here instead of rdr when set
+    bytes: Option<&'a [u8]>,
+    // loaded chunk when it is stored in bytes without compression
+    raw: Option<&'a [u8]>,
+    prefetch: Prefetch<'a>,
+}
+
+/// Threads that decompress the chunks of bundles read with
+/// [`BundleFd::set_prefetch`].
+///
+/// One pool is shared by every bundle of a run so prefetching does not start
+/// new threads for every bundle. The threads stop once the pool is dropped.
+pub struct ChunkPool {
+    jobs: mpsc::SyncSender<Job>,
+    threads: usize,
+}
+
+// decompressed chunk and the buffer of its compressed data
+type Decompressed = Result<(Vec<u8>, Vec<u8>)>;
+
+struct Job {
+    data: Vec<u8>,
+    out: Vec<u8>,
+    bundle: Option<u64>,
+    chunk: u32,
+    offset: u64,
+    done: mpsc::Sender<Decompressed>,
+}
+
+impl ChunkPool {
+    /// Start `threads` threads in `scope` that decompress with `oodle`.
+    pub fn new<'scope, 'env>(
+        scope: &'scope thread::Scope<'scope, 'env>,
+        oodle: &'env dyn Decompressor,
+        threads: usize,
+    ) -> Self {
+        let (jobs, rx) = mpsc::sync_channel(threads);
+        let rx = Arc::new(Mutex::new(rx));
+        for _ in 0..threads {
+            let rx = rx.clone();
+            scope.spawn(move || decompress_jobs(oodle, &rx));
+        }
+        Self {
+            jobs,
+            threads,
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+}
+
+fn decompress_jobs(oodle: &dyn Decompressor, jobs: &Mutex<mpsc::Receiver<Job>>) {
+    let mut scratch = Vec::new();
+    loop {
+        let Ok(job) = jobs.lock().unwrap().recv() else {
+            break;
+        };
+        let Job { data, mut out, bundle, chunk, offset, done } = job;
+        let result = if data.len() == CHUNK_SIZE {
+            Ok((data, out))
+        } else {
+            oodle.memory_size_needed()
+                .map_err(|e| Error::from_io(bundle, offset, e))
+                .and_then(|needed| {
+                    scratch.resize(needed as usize, 0);
+                    out.resize(CHUNK_SIZE, 0);
+                    let out = <&mut [u8; CHUNK_SIZE]>::try_from(&mut out[..]).unwrap();
+                    decompress_chunk(oodle, &data, out, &mut scratch, bundle, chunk, offset)
+                })
+                .map(|()| (out, data))
+        };
+        // readers stop waiting for chunks of files they skipped
+        let _ = done.send(result);
+    }
+}
+
+// chunks decompressed ahead of the read position on a ChunkPool
+#[derive(Default)]
+struct Prefetch<'a> {
+    pool: Option<&'a ChunkPool>,
+    chunks: usize,
+    // chunks sent to the pool in order, at most `chunks`
+    queue: VecDeque<(u32, mpsc::Receiver<Decompressed>)>,
+    // chunk sent to the pool next
+    next: u32,
+    // loaded chunk
+    current: Option<Vec<u8>>,
+    // buffers of consumed chunks
+    spare: Vec<Vec<u8>>,
 }
 
 impl<'a> OodleRead<'a> {
     fn new(
-        oodle: &'a oodle::Oodle,
+        oodle: &'a dyn Decompressor,
         rdr: &'a mut dyn ReadSeek,
+        bundle: Option<u64>,
         num_files: u32,
         in_buf: &'a mut [u8; CHUNK_SIZE],
         out_buf: &'a mut [u8; CHUNK_SIZE],
         scratch: &'a mut [u8],
-    ) -> io::Result<Self> {
-        rdr.seek(SeekFrom::Start(12 + 256 + u64::from(num_files) * 20)).unwrap();
-        let num_chunks = rdr.read_u32::<LE>().unwrap();
-        for _ in 0..num_chunks {
-            let _chunk_size = rdr.read_u32::<LE>().unwrap();
-        }
+    ) -> Result<Self> {
+        let table = ChunkTable::read(rdr, bundle, num_files)?;
+        Ok(Self {
+            oodle,
+            rdr,
+            bundle,
+            in_buf,
+            out_buf,
+            scratch,
+            table,
+            loaded: None,
+            next_chunk: 0,
+            position: 0,
+            bytes: None,
+            raw: None,
+            prefetch: Prefetch::default(),
+        })
+    }
 
-        let padding = align_16(rdr.stream_position().unwrap());
-        if padding > 0 {
-            rdr.seek(SeekFrom::Current(padding)).unwrap();
+    fn load(&mut self, chunk: u32) -> Result<()> {
+        self.loaded = None;
+        self.raw = None;
+
+        if let Some(pool) = self.prefetch.pool.filter(|_| self.prefetch.chunks > 1) {
+            return self.load_prefetched(pool, chunk);
         }
-        let total_size = rdr.read_u32::<LE>().unwrap() as u64;
-        let zero = rdr.read_u32::<LE>().unwrap();
 
-        if 0 != zero {
-            eprintln!("{}: {:08x} (padding {})", rdr.stream_position()? - 4, zero.swap_bytes(), padding);
-            Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected non-zero"))
+        if let Some(bytes) = self.bytes {
+            let (offset, chunk_size) = self.locate(bytes, chunk)?;
+            let data = &bytes[offset as usize..offset as usize + chunk_size];
+            if chunk_size == CHUNK_SIZE {
+                self.raw = Some(data);
+            } else {
+                decompress_chunk(self.oodle, data, self.out_buf, self.scratch, self.bundle, chunk, offset)?;
+            }
         } else {
-            let mut s = Self {
-                oodle,
-                rdr,
-                in_buf,
-                out_buf,
-                scratch,
-                offset: 0,
-                total_out: 0,
-                total_size,
-                num_chunks,
-                current: 0,
-            };
-            s.next()?;
-            Ok(s)
+            let (offset, chunk_size) = self.read_chunk(chunk)?;
+            if chunk_size == CHUNK_SIZE {
+                self.out_buf.copy_from_slice(self.in_buf);
+            } else {
+                let data = &self.in_buf[..chunk_size];
+                decompress_chunk(self.oodle, data, self.out_buf, self.scratch, self.bundle, chunk, offset)?;
+            }
         }
+
+        self.loaded = Some(chunk);
+        Ok(())
     }
 
-    fn next(&mut self) -> io::Result<bool> {
-        if self.current < self.num_chunks {
-            self.current += 1;
-            self.offset = 0;
-            let chunk_size = self.rdr.read_u32::<LE>()? as usize;
+    fn load_prefetched(&mut self, pool: &ChunkPool, chunk: u32) -> Result<()> {
+        // chunks that were skipped
+        while self.prefetch.queue.front().is_some_and(|(front, _)| *front < chunk) {
+            self.prefetch.queue.pop_front();
+        }
+        if self.prefetch.queue.front().map(|(front, _)| *front) != Some(chunk) {
+            self.prefetch.queue.clear();
+            self.prefetch.next = chunk;
+        }
 
-            let padding = align_16(self.rdr.stream_position().unwrap());
-            if padding > 0 {
-                self.rdr.seek(SeekFrom::Current(padding)).unwrap();
-            }
+        self.fill_queue(pool, chunk)?;
+        let (_, done) = self.prefetch.queue.pop_front().unwrap();
+        // the pool works on the next chunks while this one is waited for
+        self.fill_queue(pool, chunk)?;
 
-            self.rdr.read_exact(&mut self.in_buf[..chunk_size])?;
+        let (data, spare) = done.recv().expect("chunk pool stopped")?;
+        self.prefetch.spare.push(spare);
+        if let Some(old) = self.prefetch.current.replace(data) {
+            self.prefetch.spare.push(old);
+        }
+        self.loaded = Some(chunk);
+        Ok(())
+    }
 
-            if chunk_size == CHUNK_SIZE {
-                self.out_buf.copy_from_slice(self.in_buf);
-            } else {
-                let size = self.oodle.decompress(
-                    &self.in_buf[..chunk_size],
-                    &mut self.out_buf[..CHUNK_SIZE],
-                    self.scratch,
-                )?;
-                assert_eq!(size, CHUNK_SIZE as u64);
+    // send chunks to the pool until `chunks` are queued, failing only if
+    // `chunk` can not be read
+    fn fill_queue(&mut self, pool: &ChunkPool, chunk: u32) -> Result<()> {
+        while self.prefetch.queue.len() < self.prefetch.chunks
+            && (self.prefetch.next as usize) < self.table.len()
+        {
+            let next = self.prefetch.next;
+            match self.send_chunk(pool, next) {
+                Ok(done) => {
+                    self.prefetch.queue.push_back((next, done));
+                    self.prefetch.next += 1;
+                }
+                Err(e) if next == chunk => return Err(e),
+                // reported once the chunk is loaded
+                Err(_) => break,
             }
+        }
+        Ok(())
+    }
 
-            Ok(true)
-        } else {
-            Ok(false)
+    fn send_chunk(&mut self, pool: &ChunkPool, chunk: u32) -> Result<mpsc::Receiver<Decompressed>> {
+        let mut data = self.prefetch.spare.pop().unwrap_or_default();
+        data.clear();
+        let offset = match self.bytes {
+            Some(bytes) => {
+                let (offset, size) = self.locate(bytes, chunk)?;
+                data.extend_from_slice(&bytes[offset as usize..offset as usize + size]);
+                offset
+            }
+            None => {
+                let (offset, size) = self.read_chunk(chunk)?;
+                data.extend_from_slice(&self.in_buf[..size]);
+                offset
+            }
+        };
+
+        let (done, rx) = mpsc::channel();
+        let job = Job {
+            data,
+            out: self.prefetch.spare.pop().unwrap_or_default(),
+            bundle: self.bundle,
+            chunk,
+            offset,
+            done,
+        };
+        pool.jobs.send(job).expect("chunk pool stopped");
+        Ok(rx)
+    }
+
+    // read compressed chunk into in_buf returning its offset and size
+    fn read_chunk(&mut self, chunk: u32) -> Result<(u64, usize)> {
+        if chunk != self.next_chunk {
+            let offset = self.table.offsets[chunk as usize];
+            self.rdr.seek(SeekFrom::Start(offset))
+                .map_err(|e| Error::from_io(self.bundle, offset, e))?;
+        }
+        // rdr is only positioned after a chunk once it was read successfully
+        self.next_chunk = u32::MAX;
+
+        let chunk_size = read_u32(self.rdr, self.bundle)? as usize;
+
+        let position = self.rdr.stream_position()
+            .map_err(|e| Error::from_io(self.bundle, 0, e))?;
+        self.check_chunk_size(chunk, chunk_size, position - 4)?;
+
+        let padding = align_16(position);
+        if padding > 0 {
+            self.rdr.seek(SeekFrom::Current(padding))
+                .map_err(|e| Error::from_io(self.bundle, position, e))?;
+        }
+        let offset = position + padding as u64;
+
+        self.rdr.read_exact(&mut self.in_buf[..chunk_size])
+            .map_err(|e| Error::from_io(self.bundle, offset, e))?;
+
+        self.next_chunk = chunk + 1;
+        Ok((offset, chunk_size))
+    }
+
+    // find compressed chunk in bytes returning its offset and size
+    fn locate(&self, bytes: &[u8], chunk: u32) -> Result<(u64, usize)> {
+        let prefix = self.table.offsets[chunk as usize];
+        let chunk_size = bytes.get(prefix as usize..prefix as usize + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or(Error::Truncated {
+                bundle: self.bundle,
+                offset: prefix,
+            })?;
+        self.check_chunk_size(chunk, chunk_size, prefix)?;
+
+        let offset = self.table.data_offset(chunk as usize);
+        if (bytes.len() as u64) < offset + chunk_size as u64 {
+            return Err(Error::Truncated {
+                bundle: self.bundle,
+                offset: bytes.len() as u64,
+            });
+        }
+        Ok((offset, chunk_size))
+    }
+
+    // size prefix at `offset` must match the chunk table
+    fn check_chunk_size(&self, chunk: u32, chunk_size: usize, offset: u64) -> Result<()> {
+        if chunk_size > CHUNK_SIZE {
+            return Err(Error::UnexpectedValue {
+                bundle: self.bundle,
+                offset,
+                field: "chunk size",
+                value: chunk_size as u64,
+            });
+        }
+        let expected = self.table.sizes[chunk as usize];
+        if chunk_size != expected as usize {
+            return Err(Error::SizeMismatch {
+                bundle: self.bundle,
+                offset,
+                field: "chunk size",
+                expected: expected.into(),
+                found: chunk_size as u64,
+            });
+        }
+        Ok(())
+    }
+
+    // skipped chunks are never decompressed
+    fn skip(&mut self, len: u64) {
+        self.position = self.table.total_size.min(self.position + len);
+    }
+
+    // error while parsing entries is reported at offset of decompressed data
+    fn entry_error(&self, e: io::Error) -> Error {
+        Error::from_io(self.bundle, self.position, e)
+    }
+
+    fn unexpected(&self, size: u64, field: &'static str, value: u64) -> Error {
+        Error::UnexpectedValue {
+            bundle: self.bundle,
+            offset: self.position - size,
+            field,
+            value,
         }
     }
 }
 
+fn decompress_chunk(
+    oodle: &dyn Decompressor,
+    data: &[u8],
+    out: &mut [u8; CHUNK_SIZE],
+    scratch: &mut [u8],
+    bundle: Option<u64>,
+    chunk: u32,
+    offset: u64,
+) -> Result<()> {
+    let size = oodle.decompress(data, &mut out[..], scratch)
+        .map_err(|source| Error::DecompressFailed {
+            bundle,
+            offset,
+            chunk,
+            source,
+        })?;
+    if size != CHUNK_SIZE as u64 {
+        return Err(Error::SizeMismatch {
+            bundle,
+            offset,
+            field: "decompressed chunk size",
+            expected: CHUNK_SIZE as u64,
+            found: size,
+        });
+    }
+    Ok(())
+}
+
 impl<'a> Read for OodleRead<'a> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let mut fill = buf.len();
-        let len = fill;
         let mut read = 0;
 
-        while fill > 0 {
-            if self.offset == CHUNK_SIZE && !self.next()?{
-                return Ok(buf.len() - fill);
+        while read < buf.len() && self.position < self.table.total_size {
+            let chunk = (self.position / CHUNK_SIZE as u64) as u32;
+            if self.loaded != Some(chunk) {
+                self.load(chunk)?;
             }
 
-            let copy = if self.current == self.num_chunks {
-                let rem =  self.total_size as usize % CHUNK_SIZE;
-                fill.min(if rem == 0 {
-                    CHUNK_SIZE
-                } else {
-                    rem
-                } - self.offset)
-            } else {
-                fill.min(self.out_buf.len() - self.offset)
-            };
-            let offset = len - fill;
-            buf[offset..offset + copy].copy_from_slice(&self.out_buf[self.offset..self.offset + copy]);
-            fill -= copy;
-
-            self.offset += copy;
-            self.total_out += copy;
-            read += copy;
+            let offset = (self.position % CHUNK_SIZE as u64) as usize;
+            let rem = (self.table.total_size - self.position).min(CHUNK_SIZE as u64) as usize;
+            let copy = (buf.len() - read).min(rem.min(CHUNK_SIZE - offset));
+            let chunk_data = self.raw
+                .or(self.prefetch.current.as_deref())
+                .unwrap_or(&self.out_buf[..]);
+            buf[read..read + copy].copy_from_slice(&chunk_data[offset..offset + copy]);
 
-            if copy == 0 {
-                break;
-            }
+            self.position += copy as u64;
+            read += copy;
         }
 
         Ok(read)
     }
 }
 
+/// Offset of a file entry in the decompressed data of a bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryOffset {
+    pub ext: u64,
+    pub name: u64,
+    pub offset: u64,
+    /// Size of entry header and contents.
+    pub size: u64,
+}
+
 pub struct FilesIter<'a> {
     oodle: OodleRead<'a>,
+    offsets: &'a mut Option<Vec<EntryOffset>>,
     num_files: u32,
     current: u32,
 }
 
 impl<'a, 'b: 'a> FilesIter<'b> {
-    fn new(oodle: OodleRead<'b>, num_files: u32) -> Self {
+    fn new(oodle: OodleRead<'b>, offsets: &'b mut Option<Vec<EntryOffset>>, num_files: u32) -> Self {
         Self {
             oodle,
+            offsets,
             num_files,
             current: 0,
         }
     }
 
-    pub fn next_file(&'a mut self) -> io::Result<Option<Entry<'a, 'b>>> {
+    pub fn chunk_table(&self) -> &ChunkTable {
+        &self.oodle.table
+    }
+
+    pub fn next_file(&'a mut self) -> Result<Option<Entry<'a, 'b>>> {
         if self.current < self.num_files {
             self.current += 1;
-            let ext = self.oodle.read_u64::<LE>()?;
-            let name = self.oodle.read_u64::<LE>()?;
-
-            let num_variants = self.oodle.read_u32::<LE>().unwrap();
-
-            let mut failed = false;
-            for _ in 0..4 {
-                if 0 != self.oodle.read_u8().unwrap() {
-                    failed = true;
-                }
-            }
-
-            let mut variants = Vec::new();
-            let mut total_size = 0;
-            for _ in 0..num_variants {
-                let variant_kind = self.oodle.read_u32::<LE>()?;
-                let unknown1 = self.oodle.read_u8()?;
-                if unknown1 > 1 {
-                    failed = true;
-                }
-
-                if failed {
-                    eprintln!("{} {} - unexpected values",
-                        self.oodle.rdr.stream_position().unwrap(),
-                        self.oodle.total_out - 13);
-                    return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected value"));
-                }
-
-                let file_size = self.oodle.read_u32::<LE>()?;
-                let unknown2 = self.oodle.read_u8()?;
-                assert_eq!(1, unknown2);
-                let tail_size = self.oodle.read_u32::<LE>()?;
-
-                total_size += file_size + tail_size;
-
-                variants.push(Variant {
-                    kind: variant_kind,
-                    unknown1,
-                    body_size: file_size,
-                    unknown2,
-                    tail_size,
-                })
-            }
+            let rdr = &mut self.oodle;
+            let (ext, name, variants, total_size) = read_entry_header(rdr)?;
 
             if self.current == self.num_files {
-                let size = self.oodle.total_out as u64 + total_size as u64;
-                assert_eq!(size, self.oodle.total_size);
+                let size = rdr.position + total_size;
+                if size != rdr.table.total_size {
+                    return Err(Error::SizeMismatch {
+                        bundle: rdr.bundle,
+                        offset: rdr.position,
+                        field: "total size",
+                        expected: rdr.table.total_size,
+                        found: size,
+                    });
+                }
             }
             Ok(Some(Entry {
-                rdr: &mut self.oodle,
+                rdr,
                 variants,
                 remaining: total_size as usize,
                 ext,
@@ -340,8 +842,123 @@ impl<'a, 'b: 'a> FilesIter<'b> {
             Ok(None)
         }
     }
+
+    /// Offsets of all entries in the bundle.
+    ///
+    /// Only the chunks holding entry headers are decompressed to build the
+    /// offsets. They are kept by the [`BundleFd`] once computed.
+    pub fn entry_offsets(&mut self) -> Result<&[EntryOffset]> {
+        if self.offsets.is_none() {
+            let position = self.oodle.position;
+            let offsets = read_entry_offsets(&mut self.oodle, self.num_files);
+            self.oodle.position = position;
+            *self.offsets = Some(offsets?);
+        }
+
+        Ok(self.offsets.as_deref().unwrap())
+    }
+
+    /// Position the iterator so that the next call to
+    /// [`FilesIter::next_file`] returns the entry with `ext` and `name`.
+    ///
+    /// Returns `false` if there is no such entry in the bundle.
+    pub fn seek_to_entry(&mut self, ext: u64, name: u64) -> Result<bool> {
+        let found = self.entry_offsets()?
+            .iter()
+            .enumerate()
+            .find(|(_, e)| e.ext == ext && e.name == name)
+            .map(|(i, e)| (i, e.offset));
+        if let Some((i, offset)) = found {
+            self.oodle.position = offset;
+            self.current = i as u32;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Get the entry with `ext` and `name` decompressing only the chunks it
+    /// is stored in.
+    pub fn open_entry(&'a mut self, ext: u64, name: u64) -> Result<Option<Entry<'a, 'b>>> {
+        if self.seek_to_entry(ext, name)? {
+            self.next_file()
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+fn read_entry_offsets(rdr: &mut OodleRead<'_>, num_files: u32) -> Result<Vec<EntryOffset>> {
+    rdr.position = 0;
+
+    let mut offsets = Vec::with_capacity(num_files as usize);
+    for _ in 0..num_files {
+        let offset = rdr.position;
+        let (ext, name, _, total_size) = read_entry_header(rdr)?;
+        rdr.skip(total_size);
+        offsets.push(EntryOffset {
+            ext,
+            name,
+            offset,
+            size: rdr.position - offset,
+        });
+    }
+
+    if rdr.position != rdr.table.total_size {
+        return Err(Error::SizeMismatch {
+            bundle: rdr.bundle,
+            offset: rdr.position,
+            field: "total size",
+            expected: rdr.table.total_size,
+            found: rdr.position,
+        });
+    }
+
+    Ok(offsets)
+}
+
+fn read_entry_header(rdr: &mut OodleRead<'_>) -> Result<(u64, u64, Vec<Variant>, u64)> {
+    let ext = rdr.read_u64::<LE>().map_err(|e| rdr.entry_error(e))?;
+    let name = rdr.read_u64::<LE>().map_err(|e| rdr.entry_error(e))?;
+
+    let num_variants = rdr.read_u32::<LE>().map_err(|e| rdr.entry_error(e))?;
+
+    let padding = rdr.read_u32::<LE>().map_err(|e| rdr.entry_error(e))?;
+    if padding != 0 {
+        return Err(rdr.unexpected(4, "entry padding", padding.into()));
+    }
+
+    let mut variants = Vec::new();
+    let mut total_size = 0;
+    for _ in 0..num_variants {
+        let variant_kind = rdr.read_u32::<LE>().map_err(|e| rdr.entry_error(e))?;
+        let unknown1 = rdr.read_u8().map_err(|e| rdr.entry_error(e))?;
+        if unknown1 > 1 {
+            return Err(rdr.unexpected(1, "variant unknown1", unknown1.into()));
+        }
+
+        let file_size = rdr.read_u32::<LE>().map_err(|e| rdr.entry_error(e))?;
+        let unknown2 = rdr.read_u8().map_err(|e| rdr.entry_error(e))?;
+        if unknown2 != 1 {
+            return Err(rdr.unexpected(1, "variant unknown2", unknown2.into()));
+        }
+        let tail_size = rdr.read_u32::<LE>().map_err(|e| rdr.entry_error(e))?;
+
+        total_size += u64::from(file_size) + u64::from(tail_size);
+
+        variants.push(Variant {
+            kind: variant_kind,
+            unknown1,
+            body_size: file_size,
+            unknown2,
+            tail_size,
+        })
+    }
+
+    Ok((ext, name, variants, total_size))
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Variant {
     pub kind: u32,
     pub unknown1: u8,
@@ -362,19 +979,235 @@ impl<'a, 'b: 'a> Entry<'a, 'b> {
     pub fn variants(&self) -> &[Variant] {
         &self.variants
     }
+
+    /// Hash of the bundle the entry is read from, if it is known.
+    pub fn bundle(&self) -> Option<u64> {
+        self.rdr.bundle
+    }
 }
 
 impl<'a, 'b: 'a> Read for Entry<'a, 'b> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         let copy = self.remaining.min(buf.len());
-        self.remaining -= copy;
-        self.rdr.read(&mut buf[..copy])
+        let read = self.rdr.read(&mut buf[..copy])?;
+        self.remaining -= read;
+        Ok(read)
     }
 }
 
 impl<'a, 'b: 'a> Drop for Entry<'a, 'b> {
     fn drop(&mut self) {
-        // TODO add empty method
-        io::copy(&mut self.rdr.take(self.remaining as u64), &mut io::sink()).unwrap();
+        self.rdr.skip(self.remaining as u64);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::*;
+    use crate::decompress::Stored;
+    use crate::writer::BundleWriter;
+
+    fn bundle() -> Vec<u8> {
+        let mut writer = BundleWriter::new(8);
+        writer.add_file(1, 10, 0)
+            .variant(0, 0, b"first", b"");
+        writer.add_file(2, 20, 0)
+            .variant(5, 1, &[7; CHUNK_SIZE + 100], b"tail");
+        writer.add_file(3, 30, 0)
+            .variant(0, 0, b"english", b"")
+            .variant(1, 0, b"spanish", b"");
+        let mut out = Vec::new();
+        writer.write(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn files() {
+        let bundle = bundle();
+        let mut rdr = Cursor::new(&bundle[..]);
+        let mut fd = BundleFd::new(None, &mut rdr).unwrap();
+        let mut scratch = Vec::new();
+        let mut files = fd.files(&Stored, &mut scratch).unwrap();
+        assert_eq!(2, files.chunk_table().len());
+
+        let mut data = Vec::new();
+        let mut found = Vec::new();
+        while let Some(mut entry) = files.next_file().unwrap() {
+            data.clear();
+            entry.read_to_end(&mut data).unwrap();
+            let variants = entry.variants()
+                .iter()
+                .map(|v| (v.kind, v.unknown1, v.body_size, v.tail_size))
+                .collect::<Vec<_>>();
+            found.push((entry.ext, entry.name, variants, data.len()));
+        }
+        assert_eq!(found, [
+            (1, 10, vec![(0, 0, 5, 0)], 5),
+            (2, 20, vec![(5, 1, CHUNK_SIZE as u32 + 100, 4)], CHUNK_SIZE + 104),
+            (3, 30, vec![(0, 0, 7, 0), (1, 0, 7, 0)], 14),
+        ]);
+    }
+
+    #[test]
+    fn from_bytes() {
+        let bundle = bundle();
+        let mut rdr = Cursor::new(&bundle[..]);
+        let mut fd = BundleFd::from_bytes(None, &mut rdr).unwrap();
+        let mut scratch = Vec::new();
+        let mut files = fd.files(&Stored, &mut scratch).unwrap();
+
+        let mut data = Vec::new();
+        files.open_entry(2, 20).unwrap().unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(CHUNK_SIZE + 104, data.len());
+        assert_eq!(b"tail", &data[CHUNK_SIZE + 100..]);
+        let mut data = Vec::new();
+        files.next_file().unwrap().unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(b"englishspanish", &data[..]);
+
+        // truncated data of the last chunk
+        let mut rdr = Cursor::new(&bundle[..bundle.len() - 1]);
+        let mut fd = BundleFd::from_bytes(None, &mut rdr).unwrap();
+        let mut files = fd.files(&Stored, &mut scratch).unwrap();
+        assert!(matches!(files.open_entry(3, 30), Err(Error::Truncated { .. })));
+    }
+
+    #[test]
+    fn prefetch() {
+        let mut writer = BundleWriter::new(8);
+        for i in 0..6 {
+            writer.add_file(1, i, 0)
+                .variant(0, 0, &vec![i as u8; CHUNK_SIZE / 2 + 1], b"");
+        }
+        let mut bundle = Vec::new();
+        writer.write(&mut bundle).unwrap();
+
+        thread::scope(|s| {
+            let pool = ChunkPool::new(s, &Stored, 2);
+            for prefetch in [2, 4] {
+                let mut rdr = Cursor::new(&bundle[..]);
+                let mut fd = BundleFd::new(None, &mut rdr).unwrap();
+                fd.set_prefetch(&pool, prefetch);
+                let mut scratch = Vec::new();
+                let mut files = fd.files(&Stored, &mut scratch).unwrap();
+                let mut data = Vec::new();
+                while let Some(mut entry) = files.next_file().unwrap() {
+                    // odd files are skipped
+                    if entry.name % 2 == 0 {
+                        data.clear();
+                        entry.read_to_end(&mut data).unwrap();
+                        assert!(data.iter().all(|&b| b == entry.name as u8));
+                    }
+                }
+
+                // going back to the first chunk
+                let mut data = Vec::new();
+                files.open_entry(1, 0).unwrap().unwrap().read_to_end(&mut data).unwrap();
+                assert_eq!(CHUNK_SIZE / 2 + 1, data.len());
+            }
+        });
+    }
+
+    #[test]
+    fn open_entry() {
+        let bundle = bundle();
+        let mut rdr = Cursor::new(&bundle[..]);
+        let mut fd = BundleFd::new(None, &mut rdr).unwrap();
+        let mut scratch = Vec::new();
+        let mut files = fd.files(&Stored, &mut scratch).unwrap();
+
+        let mut data = Vec::new();
+        files.open_entry(3, 30).unwrap().unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(b"englishspanish", &data[..]);
+
+        data.clear();
+        files.open_entry(1, 10).unwrap().unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(b"first", &data[..]);
+
+        // iteration continues after the opened entry
+        assert_eq!(2, files.next_file().unwrap().unwrap().ext);
+        assert!(files.open_entry(1, 20).unwrap().is_none());
+        drop(files);
+
+        let offsets = fd.entry_offsets().unwrap();
+        assert_eq!(3, offsets.len());
+        assert_eq!(0, offsets[0].offset);
+        assert_eq!(offsets[0].size, offsets[1].offset);
+    }
+
+    #[test]
+    fn truncated() {
+        let bundle = bundle();
+        let mut rdr = Cursor::new(&bundle[..bundle.len() - 16]);
+        let mut fd = BundleFd::new(Some(0xab), &mut rdr).unwrap();
+        let mut scratch = Vec::new();
+        let mut files = fd.files(&Stored, &mut scratch).unwrap();
+        files.next_file().unwrap();
+        let e = files.next_file().unwrap().unwrap()
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        match Error::from_io(None, 0, e) {
+            Error::Truncated { bundle, .. } => assert_eq!(Some(0xab), bundle),
+            _ => panic!("expected truncated bundle"),
+        };
+
+        let mut rdr = Cursor::new(&bundle[..10]);
+        assert!(matches!(
+            BundleFd::new(None, &mut rdr),
+            Err(Error::Truncated { offset: 8, .. }),
+        ));
+    }
+
+    #[test]
+    fn verify() {
+        let bundle = bundle();
+        let mut scratch = Vec::new();
+        let mut rdr = Cursor::new(&bundle[..]);
+        BundleFd::new(None, &mut rdr).unwrap().verify(&Stored, &mut scratch).unwrap();
+
+        let mut rdr = Cursor::new(&bundle[..]);
+        let table = BundleFd::new(None, &mut rdr).unwrap().chunk_table().unwrap();
+        let prefix = table.offsets[1] as usize;
+
+        // size prefix of the second chunk
+        let mut corrupt = bundle.clone();
+        corrupt[prefix] ^= 1;
+        let mut rdr = Cursor::new(&corrupt[..]);
+        let mut fd = BundleFd::new(None, &mut rdr).unwrap();
+        assert!(matches!(
+            fd.verify(&Stored, &mut scratch),
+            Err(Error::SizeMismatch { field: "chunk size", offset, .. }) if offset == prefix as u64,
+        ));
+
+        // padding after the size prefix
+        let mut corrupt = bundle.clone();
+        corrupt[prefix + 4] = 1;
+        let mut rdr = Cursor::new(&corrupt[..]);
+        let mut fd = BundleFd::new(None, &mut rdr).unwrap();
+        assert!(matches!(
+            fd.verify(&Stored, &mut scratch),
+            Err(Error::UnexpectedValue { field: "padding", offset, .. }) if offset == prefix as u64 + 4,
+        ));
+
+        // trailing data
+        let mut corrupt = bundle.clone();
+        corrupt.push(0);
+        let mut rdr = Cursor::new(&corrupt[..]);
+        let mut fd = BundleFd::new(None, &mut rdr).unwrap();
+        assert!(matches!(
+            fd.verify(&Stored, &mut scratch),
+            Err(Error::SizeMismatch { field: "bundle size", .. }),
+        ));
+    }
+
+    #[test]
+    fn unknown_version() {
+        let mut bundle = bundle();
+        bundle[0] = 9;
+        let mut rdr = Cursor::new(&bundle[..]);
+        assert!(matches!(
+            BundleFd::new(None, &mut rdr),
+            Err(Error::UnknownVersion { offset: 0, .. }),
+        ));
     }
 }
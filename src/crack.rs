@@ -0,0 +1,323 @@
+//! Candidate names generated from templates for `limn crack`.
+//!
+//! A template is text with placeholders. `{list}` takes every word of the
+//! wordlist named `list` and `{name:N}` every number with `N` digits. A
+//! placeholder used more than once has the same value everywhere, so
+//! `content/characters/{word}/{word}_{num:2}` yields
+//! `content/characters/ogryn/ogryn_00` to `content/characters/ogryn/ogryn_99`
+//! for the word `ogryn`.
+//!
+//! Candidates are numbered with the last placeholder changing fastest. A
+//! search reports matches in that order so an interrupted search can resume
+//! from the last position it reported.
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::harvest::Match;
+use crate::harvest::Targets;
+use crate::hash::murmur_hash64a;
+use crate::placeholder;
+use crate::placeholder::Token;
+
+/// Candidates searched by a thread at a time.
+const BLOCK_SIZE: u64 = 0x10000;
+
+enum Part {
+    Literal(String),
+    Var(usize),
+}
+
+enum Domain<'w> {
+    Words(&'w [String]),
+    Digits(usize),
+}
+
+impl Domain<'_> {
+    fn len(&self) -> Option<u64> {
+        match self {
+            Self::Words(words) => Some(words.len() as u64),
+            Self::Digits(width) => 10_u64.checked_pow(*width as u32),
+        }
+    }
+}
+
+pub struct Template<'w> {
+    parts: Vec<Part>,
+    vars: Vec<Domain<'w>>,
+    len: u64,
+}
+
+impl<'w> Template<'w> {
+    pub fn parse(template: &str, wordlists: &'w HashMap<String, Vec<String>>) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+
+        let mut parts = Vec::new();
+        let mut names = Vec::new();
+        let mut vars = Vec::new();
+        for token in placeholder::tokenize(template)? {
+            let placeholder = match token {
+                Token::Literal(s) => {
+                    parts.push(Part::Literal(s.to_string()));
+                    continue;
+                }
+                Token::Placeholder(placeholder) => placeholder,
+            };
+
+            let (name, domain) = match placeholder.split_once(':') {
+                Some((name, width)) => match width.parse() {
+                    Ok(width @ 1..=19) => (name, Domain::Digits(width)),
+                    _ => return Err(invalid(format!("{{{placeholder}}} needs 1 to 19 digits"))),
+                },
+                None => match wordlists.get(placeholder) {
+                    Some(words) => (placeholder, Domain::Words(words)),
+                    None => return Err(invalid(format!("no wordlist named {placeholder:?}"))),
+                },
+            };
+            let var = match names.iter().position(|n| *n == name) {
+                Some(var) => var,
+                None => {
+                    names.push(name);
+                    vars.push(domain);
+                    vars.len() - 1
+                }
+            };
+            parts.push(Part::Var(var));
+        }
+
+        let len = vars.iter()
+            .try_fold(1_u64, |len, var| len.checked_mul(var.len()?))
+            .ok_or_else(|| invalid(format!("{template:?} has too many candidates")))?;
+        Ok(Self {
+            parts,
+            vars,
+            len,
+        })
+    }
+
+    /// Number of candidates.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Hash of the values every placeholder takes. Positions of a search are
+    /// only valid for templates with the same hash, it changes with the words
+    /// or the order of a wordlist.
+    pub fn domain_hash(&self) -> u64 {
+        let mut data = Vec::new();
+        for var in &self.vars {
+            match var {
+                Domain::Words(words) => {
+                    data.extend_from_slice(&(words.len() as u64).to_le_bytes());
+                    for word in *words {
+                        data.extend_from_slice(word.as_bytes());
+                        data.push(b'\n');
+                    }
+                }
+                Domain::Digits(width) => {
+                    data.push(b'#');
+                    data.extend_from_slice(&(*width as u64).to_le_bytes());
+                }
+            }
+        }
+        murmur_hash64a(&data, 0)
+    }
+
+    /// Write candidate `index` to `out`.
+    pub fn candidate(&self, index: u64, out: &mut String) {
+        let digits = self.digits(index);
+        self.write(&digits, out);
+    }
+
+    // value of every placeholder for candidate `index`
+    fn digits(&self, mut index: u64) -> Vec<u64> {
+        let mut digits = vec![0; self.vars.len()];
+        for (digit, var) in digits.iter_mut().zip(&self.vars).rev() {
+            let len = var.len().unwrap();
+            *digit = index % len;
+            index /= len;
+        }
+        digits
+    }
+
+    fn write(&self, digits: &[u64], out: &mut String) {
+        out.clear();
+        for part in &self.parts {
+            match part {
+                Part::Literal(s) => out.push_str(s),
+                Part::Var(var) => match self.vars[*var] {
+                    Domain::Words(words) => out.push_str(&words[digits[*var] as usize]),
+                    Domain::Digits(width) => write!(out, "{:0width$}", digits[*var]).unwrap(),
+                },
+            }
+        }
+    }
+
+    /// Candidates in `start..end` matching `targets`, in order.
+    pub fn search_range(&self, start: u64, end: u64, targets: &Targets) -> Vec<Found> {
+        let mut found = Vec::new();
+        if start >= end {
+            return found;
+        }
+
+        let mut digits = self.digits(start);
+        let mut name = String::new();
+        for index in start..end {
+            self.write(&digits, &mut name);
+            if let Some(m) = targets.find(&name) {
+                found.push(Found {
+                    index,
+                    name: name.clone(),
+                    found: m,
+                });
+            }
+
+            // count up with the last placeholder changing fastest
+            for (digit, var) in digits.iter_mut().zip(&self.vars).rev() {
+                *digit += 1;
+                if *digit < var.len().unwrap() {
+                    break;
+                }
+                *digit = 0;
+            }
+        }
+        found
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Found {
+    /// Number of the candidate in its template.
+    pub index: u64,
+    pub name: String,
+    pub found: Match,
+}
+
+/// Search the candidates of `template` from `start` on on `num_threads`
+/// threads.
+///
+/// `progress` is called in order of the candidates with the matches of each
+/// finished block and the position the search would resume at. The search
+/// stops at the first error returned by `progress`.
+pub fn search(
+    template: &Template<'_>,
+    targets: &Targets,
+    start: u64,
+    num_threads: usize,
+    mut progress: impl FnMut(u64, Vec<Found>) -> io::Result<()>,
+) -> io::Result<()> {
+    let len = template.len();
+    let next = AtomicU64::new(start);
+    let stop = AtomicBool::new(false);
+    let (tx, rx) = mpsc::channel();
+    thread::scope(|s| {
+        for _ in 0..num_threads.max(1) {
+            let tx = tx.clone();
+            let (next, stop) = (&next, &stop);
+            s.spawn(move || {
+                while !stop.load(Ordering::Acquire) {
+                    let block = next.fetch_add(BLOCK_SIZE, Ordering::AcqRel);
+                    if block >= len {
+                        break;
+                    }
+                    let found = template.search_range(block, len.min(block + BLOCK_SIZE), targets);
+                    if tx.send((block, found)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        // blocks finish out of order and are reported once all before are done
+        let mut finished = BTreeMap::new();
+        let mut position = start;
+        for (block, found) in rx {
+            finished.insert(block, found);
+            while let Some(found) = finished.remove(&position) {
+                position = len.min(position + BLOCK_SIZE);
+                if let Err(e) = progress(position, found) {
+                    stop.store(true, Ordering::Release);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn template() {
+        let wordlists = HashMap::from([
+            ("word".to_string(), vec!["ogryn".to_string(), "zealot".to_string()]),
+        ]);
+        let template = Template::parse("content/{word}/{word}_{num:2}", &wordlists).unwrap();
+        assert_eq!(200, template.len());
+        let mut name = String::new();
+        template.candidate(0, &mut name);
+        assert_eq!("content/ogryn/ogryn_00", name);
+        template.candidate(107, &mut name);
+        assert_eq!("content/zealot/zealot_07", name);
+
+        assert!(Template::parse("{words}", &wordlists).is_err());
+        assert!(Template::parse("{num:20}", &wordlists).is_err());
+        assert!(Template::parse("{word", &wordlists).is_err());
+        assert_eq!(1, Template::parse("plain", &wordlists).unwrap().len());
+
+        let hash = template.domain_hash();
+        assert_eq!(hash, Template::parse("a/{word}{num:2}", &wordlists).unwrap().domain_hash());
+        assert_ne!(hash, Template::parse("{word}_{num:3}", &wordlists).unwrap().domain_hash());
+        let reordered = HashMap::from([
+            ("word".to_string(), vec!["zealot".to_string(), "ogryn".to_string()]),
+        ]);
+        let template = Template::parse("content/{word}/{word}_{num:2}", &reordered).unwrap();
+        assert_ne!(hash, template.domain_hash());
+    }
+
+    #[test]
+    fn search_blocks() {
+        let wordlists = HashMap::new();
+        let template = Template::parse("a{x:3}{y:3}", &wordlists).unwrap();
+        let mut targets = Targets::default();
+        targets.insert(murmur_hash64a(b"a000999", 0));
+        targets.insert(murmur_hash64a(b"a123456", 0));
+        targets.insert_short((murmur_hash64a(b"a999000", 0) >> 32) as u32);
+
+        let mut positions = Vec::new();
+        let mut found = Vec::new();
+        search(&template, &targets, 500, 4, |position, mut f| {
+            positions.push(position);
+            found.append(&mut f);
+            Ok(())
+        }).unwrap();
+        assert_eq!(Some(&1_000_000), positions.last());
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(vec![
+            Found { index: 999, name: "a000999".to_string(), found: Match::Name },
+            Found { index: 123456, name: "a123456".to_string(), found: Match::Name },
+            Found { index: 999000, name: "a999000".to_string(), found: Match::Short },
+        ], found);
+
+        // resuming after the first match skips it
+        let mut found = Vec::new();
+        search(&template, &targets, 1000, 2, |_, mut f| {
+            found.append(&mut f);
+            Ok(())
+        }).unwrap();
+        assert_eq!(2, found.len());
+    }
+}
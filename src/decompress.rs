@@ -0,0 +1,34 @@
+use std::io;
+
+/// Decompressor for bundle chunks and texture streaming data.
+pub trait Decompressor: Sync {
+    /// Size of the scratch memory passed to [`Decompressor::decompress`].
+    fn memory_size_needed(&self) -> io::Result<u64>;
+
+    /// Decompress `data` into `out` returning the number of bytes written.
+    ///
+    /// Fails unless `out` was filled completely.
+    fn decompress(&self, data: &[u8], out: &mut [u8], scratch: &mut [u8]) -> io::Result<u64>;
+}
+
+/// Decompressor for data that is stored without compression.
+///
+/// Bundles written with raw chunks, like the ones from
+/// [`BundleWriter`](crate::writer::BundleWriter), can be read without Oodle.
+pub struct Stored;
+
+impl Decompressor for Stored {
+    fn memory_size_needed(&self) -> io::Result<u64> {
+        Ok(0)
+    }
+
+    fn decompress(&self, data: &[u8], out: &mut [u8], _scratch: &mut [u8]) -> io::Result<u64> {
+        if data.len() != out.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                "compressed data can not be read without a decompressor"));
+        }
+
+        out.copy_from_slice(data);
+        Ok(out.len() as u64)
+    }
+}
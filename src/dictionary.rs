@@ -0,0 +1,435 @@
+//! Names for reverse hash lookup loaded from dictionary files.
+//!
+//! Three formats are read, picked by the extension of the file:
+//! - `.txt` one name per line
+//! - `.csv` `hash,name` per line with the hash as 16 hex digits, further
+//!   columns such as the source written by `limn harvest` are ignored
+//! - `.json` an object of `"hash": "name"` pairs or an array of names
+//!
+//! Several files can be loaded into one [`Dictionary`]. When two different
+//! names hash to the same value the name loaded first is kept.
+use std::collections::hash_map;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::str::CharIndices;
+
+use crate::hash::murmur_hash64a;
+use crate::hash::MurmurHash;
+use crate::hash::MurmurHash32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Csv,
+    Json,
+}
+
+impl Format {
+    /// Format of a file from its extension. Unknown extensions are read as
+    /// plain text.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => Self::Csv,
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Text,
+        }
+    }
+}
+
+/// Result of adding a single name to a [`Dictionary`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum Insert {
+    Added,
+    /// The name was already known.
+    Duplicate,
+    /// A different name with the same hash was already known.
+    Collision { existing: String },
+    /// The precomputed hash does not belong to the name. The name is skipped.
+    Mismatch,
+}
+
+/// What happened while loading one dictionary file.
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    pub added: usize,
+    pub duplicates: usize,
+    /// `(hash, kept name, skipped name)`
+    pub collisions: Vec<(u64, String, String)>,
+    /// `(precomputed hash, name)`
+    pub mismatches: Vec<(u64, String)>,
+}
+
+#[derive(Debug, Default)]
+pub struct Dictionary {
+    names: HashMap<u64, String>,
+}
+
+impl Dictionary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn get(&self, hash: u64) -> Option<&str> {
+        self.names.get(&hash).map(|name| name.as_str())
+    }
+
+    /// Add `name`. `hash` is checked against the hash of `name` if given.
+    pub fn insert(&mut self, name: &str, hash: Option<u64>) -> Insert {
+        let computed = murmur_hash64a(name.as_bytes(), 0);
+        if hash.is_some_and(|hash| hash != computed) {
+            return Insert::Mismatch;
+        }
+
+        match self.names.entry(computed) {
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(name.to_string());
+                Insert::Added
+            }
+            hash_map::Entry::Occupied(entry) if entry.get() == name => Insert::Duplicate,
+            hash_map::Entry::Occupied(entry) => Insert::Collision {
+                existing: entry.get().clone(),
+            },
+        }
+    }
+
+    /// Load a dictionary file in the format given by its extension.
+    pub fn load(&mut self, path: &Path) -> io::Result<LoadReport> {
+        let data = fs::read_to_string(path)?;
+        self.parse(&data, Format::from_path(path))
+    }
+
+    /// Add every name in `data`. Syntax errors return
+    /// [`io::ErrorKind::InvalidData`] and leave the names before the error
+    /// added.
+    pub fn parse(&mut self, data: &str, format: Format) -> io::Result<LoadReport> {
+        let mut report = LoadReport::default();
+        let mut add = |name: &str, hash: Option<u64>| {
+            match self.insert(name, hash) {
+                Insert::Added => report.added += 1,
+                Insert::Duplicate => report.duplicates += 1,
+                Insert::Collision { existing } => {
+                    let hash = murmur_hash64a(name.as_bytes(), 0);
+                    report.collisions.push((hash, existing, name.to_string()));
+                }
+                Insert::Mismatch => report.mismatches.push((hash.unwrap(), name.to_string())),
+            }
+        };
+
+        match format {
+            Format::Text => {
+                for name in data.lines() {
+                    if !name.is_empty() {
+                        add(name, None);
+                    }
+                }
+            }
+            Format::Csv => {
+                for (i, line) in data.lines().enumerate() {
+                    if line.is_empty() {
+                        continue;
+                    }
+                    let (hash, name) = line.split_once(',')
+                        .ok_or_else(|| invalid(format!("line {}: expected hash,name", i + 1)))?;
+                    let hash = match parse_hash(hash) {
+                        Some(hash) => hash,
+                        // header line
+                        None if i == 0 => continue,
+                        None => return Err(invalid(format!("line {}: invalid hash {hash:?}", i + 1))),
+                    };
+                    let name = csv_field(name)
+                        .ok_or_else(|| invalid(format!("line {}: invalid quoted name", i + 1)))?;
+                    add(&name, Some(hash));
+                }
+            }
+            Format::Json => {
+                let mut json = Json { s: data, pos: 0 };
+                match json.next_byte()? {
+                    b'{' => {
+                        json.list(b'}', |json| {
+                            let hash = json.string()?;
+                            let hash = parse_hash(&hash)
+                                .ok_or_else(|| json.error(&format!("invalid hash {hash:?}")))?;
+                            json.expect(b':')?;
+                            add(&json.string()?, Some(hash));
+                            Ok(())
+                        })?;
+                    }
+                    b'[' => {
+                        json.list(b']', |json| {
+                            add(&json.string()?, None);
+                            Ok(())
+                        })?;
+                    }
+                    _ => return Err(json.error("expected an object or array")),
+                }
+                json.skip_whitespace();
+                if json.pos != data.len() {
+                    return Err(json.error("trailing characters"));
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Hashes and names in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &str)> {
+        self.names.iter().map(|(&hash, name)| (hash, name.as_str()))
+    }
+
+    /// Map for [`crate::ExtractOptions::dictionary`].
+    pub fn names(&self) -> HashMap<MurmurHash, &str> {
+        self.names.iter()
+            .map(|(&hash, name)| (MurmurHash(hash), name.as_str()))
+            .collect()
+    }
+
+    /// Map for [`crate::ExtractOptions::dictionary_short`].
+    pub fn short_names(&self) -> HashMap<MurmurHash32, &str> {
+        self.names.iter()
+            .map(|(&hash, name)| (MurmurHash(hash).clone_short(), name.as_str()))
+            .collect()
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// 16 hex digits with an optional 0x prefix
+fn parse_hash(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    if s.len() != 16 {
+        return None;
+    }
+    u64::from_str_radix(s, 16).ok()
+}
+
+/// Write `hash,name,source` lines in the format read for `.csv` files.
+pub fn write_csv<'n>(
+    mut out: impl Write,
+    rows: impl IntoIterator<Item = (u64, &'n str, &'n str)>,
+) -> io::Result<()> {
+    writeln!(out, "hash,name,source")?;
+    for (hash, name, source) in rows {
+        write_csv_row(&mut out, hash, name, source)?;
+    }
+    Ok(())
+}
+
+/// Write a single line of [`write_csv`], for appending to a file.
+pub fn write_csv_row(mut out: impl Write, hash: u64, name: &str, source: &str) -> io::Result<()> {
+    write!(out, "{hash:016x},")?;
+    write_csv_field(&mut out, name)?;
+    write!(out, ",")?;
+    write_csv_field(&mut out, source)?;
+    writeln!(out)
+}
+
+/// Write `s` as a CSV field, quoted if needed.
+pub fn write_csv_field(mut out: impl Write, s: &str) -> io::Result<()> {
+    if s.contains([',', '"']) || s.starts_with(' ') || s.ends_with(' ') {
+        write!(out, "\"{}\"", s.replace('"', "\"\""))
+    } else {
+        write!(out, "{s}")
+    }
+}
+
+// first field of `s`, quoted fields use "" for a quote
+fn csv_field(s: &str) -> Option<String> {
+    let Some(quoted) = s.strip_prefix('"') else {
+        return Some(s.split(',').next().unwrap().to_string());
+    };
+
+    let mut field = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c != '"' {
+            field.push(c);
+            continue;
+        }
+        // a quote is either escaped by a second one or ends the field
+        match chars.next() {
+            Some('"') => field.push('"'),
+            Some(',') | None => return Some(field),
+            Some(_) => return None,
+        }
+    }
+    None
+}
+
+// only strings, arrays and objects are needed for dictionaries
+struct Json<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl Json<'_> {
+    fn error(&self, msg: &str) -> io::Error {
+        invalid(format!("offset {}: {msg}", self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.s[self.pos..];
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn next_byte(&mut self) -> io::Result<u8> {
+        self.skip_whitespace();
+        let b = *self.s.as_bytes().get(self.pos)
+            .ok_or_else(|| self.error("unexpected end"))?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn expect(&mut self, expected: u8) -> io::Result<()> {
+        if self.next_byte()? != expected {
+            self.pos -= 1;
+            return Err(self.error(&format!("expected '{}'", expected as char)));
+        }
+        Ok(())
+    }
+
+    // items separated by commas after the opening bracket was read
+    fn list(&mut self, end: u8, mut item: impl FnMut(&mut Self) -> io::Result<()>) -> io::Result<()> {
+        self.skip_whitespace();
+        if self.s.as_bytes().get(self.pos) == Some(&end) {
+            self.pos += 1;
+            return Ok(());
+        }
+        loop {
+            item(self)?;
+            match self.next_byte()? {
+                b',' => (),
+                b if b == end => return Ok(()),
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.error(&format!("expected ',' or '{}'", end as char)));
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        self.expect(b'"')?;
+        let mut out = String::new();
+        let mut chars = self.s[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(out);
+                }
+                '\\' => {
+                    let c = match chars.next().map(|(_, c)| c) {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\x08',
+                        Some('f') => '\x0c',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let take = |chars: &mut CharIndices, n| {
+                                chars.take(n).map(|(_, c)| c).collect::<String>()
+                            };
+                            let high = u16::from_str_radix(&take(&mut chars, 4), 16).ok();
+                            let units = match high {
+                                // surrogate pairs are written as two escapes
+                                Some(0xd800..=0xdbff) => {
+                                    let low = match take(&mut chars, 2).as_str() {
+                                        "\\u" => u16::from_str_radix(&take(&mut chars, 4), 16).ok(),
+                                        _ => None,
+                                    };
+                                    vec![high, low]
+                                }
+                                _ => vec![high],
+                            };
+                            let units = units.into_iter().collect::<Option<Vec<_>>>();
+                            match units.and_then(|u| char::decode_utf16(u).next()?.ok()) {
+                                Some(c) => c,
+                                None => {
+                                    self.pos += i;
+                                    return Err(self.error("invalid unicode escape"));
+                                }
+                            }
+                        }
+                        _ => {
+                            self.pos += i;
+                            return Err(self.error("invalid escape"));
+                        }
+                    };
+                    out.push(c);
+                }
+                c => out.push(c),
+            }
+        }
+        self.pos = self.s.len();
+        Err(self.error("unterminated string"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formats() {
+        let lua = murmur_hash64a(b"scripts/main", 0);
+        let mut dict = Dictionary::new();
+
+        let report = dict.parse("scripts/main\n\nscripts/main\n", Format::Text).unwrap();
+        assert_eq!((1, 1), (report.added, report.duplicates));
+
+        let csv = format!("hash,name\n{lua:016x},scripts/main\n0x{:016x},\"a,\"\"b\"\"\"\n0000000000000001,c\n",
+            murmur_hash64a(b"a,\"b\"", 0));
+        let report = dict.parse(&csv, Format::Csv).unwrap();
+        assert_eq!((1, 1), (report.added, report.duplicates));
+        assert_eq!(vec![(1, "c".to_string())], report.mismatches);
+        assert_eq!(Some("a,\"b\""), dict.get(murmur_hash64a(b"a,\"b\"", 0)));
+
+        let mut csv = Vec::new();
+        let hash = murmur_hash64a(b"d,\"", 0);
+        write_csv(&mut csv, [(hash, "d,\"", "lua constant")]).unwrap();
+        assert_eq!(format!("hash,name,source\n{hash:016x},\"d,\"\"\",lua constant\n"), String::from_utf8_lossy(&csv));
+        assert_eq!(1, dict.parse(std::str::from_utf8(&csv).unwrap(), Format::Csv).unwrap().added);
+
+        let json = format!(" {{\"{:016x}\": \"x\\\"\\u00e9\\ud83d\\ude00\"}} ", murmur_hash64a("x\"é😀".as_bytes(), 0));
+        assert_eq!(1, dict.parse(&json, Format::Json).unwrap().added);
+        assert_eq!(1, dict.parse("[\"y\", \"scripts/main\"]", Format::Json).unwrap().added);
+        assert_eq!(0, dict.parse("[]", Format::Json).unwrap().added);
+        assert_eq!(5, dict.len());
+        assert_eq!(Some(&"scripts/main"), dict.names().get(&MurmurHash(lua)));
+
+        let err = dict.parse("[\"z\" \"w\"]", Format::Json).unwrap_err();
+        assert_eq!("offset 5: expected ',' or ']'", err.to_string());
+        assert_eq!(0, dict.parse("zz,name", Format::Csv).unwrap().added);
+        let err = dict.parse("hash,name\nzz,name", Format::Csv).unwrap_err();
+        assert_eq!("line 2: invalid hash \"zz\"", err.to_string());
+    }
+
+    #[test]
+    fn collision() {
+        let mut dict = Dictionary::new();
+        dict.names.insert(murmur_hash64a(b"b", 0), "a".to_string());
+        let report = dict.parse("b\n", Format::Text).unwrap();
+        assert_eq!(
+            vec![(murmur_hash64a(b"b", 0), "a".to_string(), "b".to_string())],
+            report.collisions,
+        );
+        assert_eq!(Some("a"), dict.get(murmur_hash64a(b"b", 0)));
+    }
+}
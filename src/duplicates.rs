@@ -0,0 +1,200 @@
+//! Files with copies in several bundles.
+//!
+//! [`Owners`] picks the bundle every file is extracted from before anything
+//! is extracted, so the output does not depend on the order threads read the
+//! bundles in. [`Conflicts`] collects the content hashes of the copies to find
+//! files whose copies differ.
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io;
+use std::io::Read;
+
+use crate::bundle::Entry;
+use crate::hash::murmur_hash64a;
+
+/// Bundle each `(ext, name)` pair is extracted from.
+///
+/// Bundles passed as priority win in their order, all others by the lowest
+/// bundle hash.
+#[derive(Debug, Default)]
+pub struct Owners {
+    priority: Vec<u64>,
+    // bundles with the pair, the owner first
+    owners: HashMap<(u64, u64), Vec<u64>>,
+}
+
+impl Owners {
+    pub fn new(priority: Vec<u64>) -> Self {
+        Self {
+            priority,
+            owners: HashMap::new(),
+        }
+    }
+
+    /// Add the `(ext, name)` pairs in the index of `bundle`.
+    pub fn add(&mut self, bundle: u64, files: impl IntoIterator<Item = (u64, u64)>) {
+        let priority = &self.priority;
+        for key in files {
+            let bundles = self.owners.entry(key).or_default();
+            let i = bundles.partition_point(|b| rank(priority, *b) < rank(priority, bundle));
+            if bundles.get(i) != Some(&bundle) {
+                bundles.insert(i, bundle);
+            }
+        }
+    }
+
+    /// Bundle the file is extracted from.
+    pub fn owner(&self, ext: u64, name: u64) -> Option<u64> {
+        self.owners.get(&(ext, name)).and_then(|bundles| bundles.first().copied())
+    }
+
+    /// Number of bundles the file is in.
+    pub fn copies(&self, ext: u64, name: u64) -> u32 {
+        self.owners.get(&(ext, name)).map(|bundles| bundles.len() as u32).unwrap_or(0)
+    }
+
+    /// Owners of the files owned by one of the `failed` bundles among their
+    /// other copies. Files without a copy in another bundle are left out.
+    pub fn reassign(&self, failed: &HashSet<u64>) -> Owners {
+        let owners = self.owners.iter()
+            .filter(|(_, bundles)| failed.contains(&bundles[0]))
+            .map(|(&key, bundles)| (key, bundles.iter()
+                .copied()
+                .filter(|b| !failed.contains(b))
+                .collect::<Vec<_>>()))
+            .filter(|(_, bundles)| !bundles.is_empty())
+            .collect();
+        Owners {
+            priority: self.priority.clone(),
+            owners,
+        }
+    }
+
+    /// Bundles that own at least one file.
+    pub fn bundles(&self) -> HashSet<u64> {
+        self.owners.values().map(|bundles| bundles[0]).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.owners.is_empty()
+    }
+}
+
+fn rank(priority: &[u64], bundle: u64) -> (usize, u64) {
+    let position = priority.iter().position(|b| *b == bundle);
+    (position.unwrap_or(priority.len()), bundle)
+}
+
+/// A file whose copies differ.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Conflict {
+    pub ext: u64,
+    pub name: u64,
+    /// `(bundle, content hash)` of every copy by bundle hash.
+    pub copies: Vec<(u64, u64)>,
+}
+
+/// Content hashes of the copies of files, see [`content_hash`].
+#[derive(Debug, Default)]
+pub struct Conflicts {
+    copies: HashMap<(u64, u64), Vec<(u64, u64)>>,
+}
+
+impl Conflicts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the `((ext, name), content hash)` of files in `bundle`.
+    pub fn add(&mut self, bundle: u64, hashes: impl IntoIterator<Item = ((u64, u64), u64)>) {
+        for (key, hash) in hashes {
+            self.copies.entry(key).or_default().push((bundle, hash));
+        }
+    }
+
+    /// Files with copies that differ, sorted by extension and name.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts = self.copies.iter()
+            .filter(|(_, copies)| copies.iter().any(|(_, hash)| *hash != copies[0].1))
+            .map(|(&(ext, name), copies)| {
+                let mut copies = copies.clone();
+                copies.sort_unstable();
+                Conflict {
+                    ext,
+                    name,
+                    copies,
+                }
+            })
+            .collect::<Vec<_>>();
+        conflicts.sort_unstable_by_key(|c| (c.ext, c.name));
+        conflicts
+    }
+}
+
+/// Hash the variants and contents of `entry` using `buf` as scratch space.
+pub fn content_hash(entry: &mut Entry<'_, '_>, buf: &mut Vec<u8>) -> io::Result<u64> {
+    buf.clear();
+    for variant in entry.variants() {
+        buf.extend_from_slice(&variant.kind.to_le_bytes());
+        buf.extend_from_slice(&variant.body_size.to_le_bytes());
+        buf.extend_from_slice(&variant.tail_size.to_le_bytes());
+    }
+    entry.read_to_end(buf)?;
+    Ok(murmur_hash64a(buf, 0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn owners() {
+        // the owner does not depend on the order bundles are added in
+        for order in [[1, 2, 3], [3, 2, 1], [2, 3, 1]] {
+            let mut owners = Owners::new(vec![3]);
+            for bundle in order {
+                let files = match bundle {
+                    1 => vec![(1, 10), (1, 11)],
+                    2 => vec![(1, 10), (1, 12)],
+                    _ => vec![(1, 11)],
+                };
+                owners.add(bundle, files);
+            }
+            assert_eq!(Some(1), owners.owner(1, 10));
+            assert_eq!(Some(3), owners.owner(1, 11));
+            assert_eq!(Some(2), owners.owner(1, 12));
+            assert_eq!(None, owners.owner(1, 13));
+            assert_eq!(2, owners.copies(1, 10));
+            assert_eq!(1, owners.copies(1, 12));
+        }
+    }
+
+    #[test]
+    fn reassign() {
+        let mut owners = Owners::new(vec![3]);
+        owners.add(1, [(1, 10), (1, 11)]);
+        owners.add(2, [(1, 10), (1, 12)]);
+        owners.add(3, [(1, 11)]);
+
+        let retry = owners.reassign(&HashSet::from([1, 3]));
+        assert_eq!(Some(2), retry.owner(1, 10));
+        // no other copy
+        assert_eq!(None, retry.owner(1, 11));
+        // owned by a bundle that did not fail
+        assert_eq!(None, retry.owner(1, 12));
+        assert_eq!(HashSet::from([2]), retry.bundles());
+        assert!(owners.reassign(&HashSet::from([2])).is_empty());
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut conflicts = Conflicts::new();
+        conflicts.add(2, [((1, 10), 100), ((1, 11), 200)]);
+        conflicts.add(1, [((1, 10), 100), ((1, 11), 201)]);
+        assert_eq!(vec![Conflict {
+            ext: 1,
+            name: 11,
+            copies: vec![(1, 201), (2, 200)],
+        }], conflicts.conflicts());
+    }
+}
@@ -0,0 +1,142 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Error from parsing a bundle.
+///
+/// Every variant carries the hash of the bundle, if it is known, and a byte
+/// offset. Errors in the header, index or chunks use the offset in the bundle
+/// file. Errors while parsing file entries use the offset in the decompressed
+/// data since that is where the entry headers live.
+#[derive(Debug)]
+pub enum Error {
+    UnknownVersion {
+        bundle: Option<u64>,
+        offset: u64,
+        header: [u8; 8],
+    },
+    Truncated {
+        bundle: Option<u64>,
+        offset: u64,
+    },
+    DecompressFailed {
+        bundle: Option<u64>,
+        offset: u64,
+        chunk: u32,
+        source: io::Error,
+    },
+    UnexpectedValue {
+        bundle: Option<u64>,
+        offset: u64,
+        field: &'static str,
+        value: u64,
+    },
+    SizeMismatch {
+        bundle: Option<u64>,
+        offset: u64,
+        field: &'static str,
+        expected: u64,
+        found: u64,
+    },
+    Io {
+        bundle: Option<u64>,
+        offset: u64,
+        source: io::Error,
+    },
+}
+
+impl Error {
+    /// Convert an IO error from reading `bundle` at `offset`.
+    ///
+    /// An [`Error`] that was wrapped in an [`io::Error`] to pass through a
+    /// [`io::Read`] implementation is unwrapped instead.
+    pub fn from_io(bundle: Option<u64>, offset: u64, e: io::Error) -> Self {
+        if e.get_ref().map(|inner| inner.is::<Error>()).unwrap_or(false) {
+            return *e.into_inner().unwrap().downcast::<Error>().unwrap();
+        }
+
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => Self::Truncated {
+                bundle,
+                offset,
+            },
+            _ => Self::Io {
+                bundle,
+                offset,
+                source: e,
+            },
+        }
+    }
+
+    pub fn bundle(&self) -> Option<u64> {
+        match *self {
+            Self::UnknownVersion { bundle, .. }
+            | Self::Truncated { bundle, .. }
+            | Self::DecompressFailed { bundle, .. }
+            | Self::UnexpectedValue { bundle, .. }
+            | Self::SizeMismatch { bundle, .. }
+            | Self::Io { bundle, .. } => bundle,
+        }
+    }
+
+    pub fn offset(&self) -> u64 {
+        match *self {
+            Self::UnknownVersion { offset, .. }
+            | Self::Truncated { offset, .. }
+            | Self::DecompressFailed { offset, .. }
+            | Self::UnexpectedValue { offset, .. }
+            | Self::SizeMismatch { offset, .. }
+            | Self::Io { offset, .. } => offset,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.bundle() {
+            Some(bundle) => write!(f, "{bundle:016x}")?,
+            None => write!(f, "anonymous bundle")?,
+        }
+        write!(f, " @ {:#x}: ", self.offset())?;
+
+        match self {
+            Self::UnknownVersion { header, .. } => {
+                write!(f, "unknown bundle version {} (header {header:02x?})", header[0])
+            }
+            Self::Truncated { .. } => f.write_str("unexpected end of bundle"),
+            Self::DecompressFailed { chunk, source, .. } => {
+                write!(f, "failed to decompress chunk {chunk}: {source}")
+            }
+            Self::UnexpectedValue { field, value, .. } => {
+                write!(f, "unexpected value {value:#x} for {field}")
+            }
+            Self::SizeMismatch { field, expected, found, .. } => {
+                write!(f, "{field} is {found:#x} but expected {expected:#x}")
+            }
+            Self::Io { source, .. } => write!(f, "{source}"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::DecompressFailed { source, .. }
+            | Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        let kind = match &e {
+            Error::Truncated { .. } => io::ErrorKind::UnexpectedEof,
+            Error::Io { source, .. } => source.kind(),
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, e)
+    }
+}
@@ -1,6 +1,6 @@
 use super::*;
 
-pub(crate) struct BonesParser;
+pub struct BonesParser;
 
 impl Extractor for BonesParser {
     fn extract(
@@ -0,0 +1,154 @@
+use std::fmt;
+
+use crate::placeholder;
+use crate::placeholder::Token;
+use super::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Bundle,
+    Ext,
+    Name,
+    NameHash,
+    Lang,
+}
+
+/// Template of the paths files are extracted to.
+///
+/// `{bundle}` is the bundle hash, `{ext}` the extension, `{name}` the name
+/// from the dictionary or the name hash, `{name_hash}` always the name hash and
+/// `{lang}` the language of a strings file, empty for other files. Converted
+/// files replace the extension of the path with their own, such as `.dds` for
+/// textures. The default is `{name}.{ext}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    parts: Vec<Part>,
+}
+
+/// Values of the placeholders of a [`Layout`].
+pub struct LayoutVars<'a> {
+    pub bundle: Option<u64>,
+    pub ext: &'a str,
+    pub name: &'a str,
+    pub name_hash: u64,
+    pub lang: &'a str,
+}
+
+impl Layout {
+    pub fn parse(template: &str) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+
+        let mut parts = Vec::new();
+        for token in placeholder::tokenize(template)? {
+            parts.push(match token {
+                Token::Literal(s) => Part::Literal(s.to_string()),
+                Token::Placeholder("bundle") => Part::Bundle,
+                Token::Placeholder("ext") => Part::Ext,
+                Token::Placeholder("name") => Part::Name,
+                Token::Placeholder("name_hash") => Part::NameHash,
+                Token::Placeholder("lang") => Part::Lang,
+                Token::Placeholder(placeholder) => return Err(invalid(format!("unknown placeholder {{{placeholder}}}, \
+                    expected {{bundle}}, {{ext}}, {{name}}, {{name_hash}} or {{lang}}"))),
+            });
+        }
+
+        if !parts.iter().any(|part| matches!(part, Part::Name | Part::NameHash)) {
+            return Err(invalid(format!("{template:?} needs {{name}} or {{name_hash}}")));
+        }
+
+        let layout = Self {
+            parts,
+        };
+        let example = layout.render(&LayoutVars {
+            bundle: Some(0),
+            ext: "ext",
+            name: "name",
+            name_hash: 0,
+            lang: "en",
+        }).to_string();
+        let example = Path::new(&example);
+        if example.has_root() || !no_escape(example) {
+            return Err(invalid(format!("{template:?} leaves the output directory")));
+        }
+        Ok(layout)
+    }
+
+    /// True for `{name}.{ext}`.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn has_lang(&self) -> bool {
+        self.parts.contains(&Part::Lang)
+    }
+
+    /// Path for `vars` relative to the output directory.
+    pub fn render<'a>(&'a self, vars: &'a LayoutVars<'a>) -> impl fmt::Display + 'a {
+        Render {
+            layout: self,
+            vars,
+        }
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            parts: vec![Part::Name, Part::Literal(".".to_string()), Part::Ext],
+        }
+    }
+}
+
+struct Render<'a> {
+    layout: &'a Layout,
+    vars: &'a LayoutVars<'a>,
+}
+
+impl fmt::Display for Render<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let vars = self.vars;
+        for part in &self.layout.parts {
+            match part {
+                Part::Literal(s) => f.write_str(s)?,
+                Part::Bundle => match vars.bundle {
+                    Some(bundle) => write!(f, "{bundle:016x}")?,
+                    None => f.write_str("unknown")?,
+                },
+                Part::Ext => f.write_str(vars.ext)?,
+                Part::Name => f.write_str(vars.name)?,
+                Part::NameHash => write!(f, "{:016x}", vars.name_hash)?,
+                Part::Lang => f.write_str(vars.lang)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn layout() {
+        let vars = LayoutVars {
+            bundle: Some(0x0123456789abcdef),
+            ext: "strings",
+            name: "content/ui/loc",
+            name_hash: 0x2f4a8724618f4c63,
+            lang: "en",
+        };
+        assert_eq!("content/ui/loc.strings", Layout::default().render(&vars).to_string());
+        assert!(Layout::parse("{name}.{ext}").unwrap().is_default());
+
+        let layout = Layout::parse("{bundle}/{lang}/{ext}/{name_hash}").unwrap();
+        assert!(layout.has_lang());
+        assert_eq!("0123456789abcdef/en/strings/2f4a8724618f4c63", layout.render(&vars).to_string());
+
+        assert!(Layout::parse("{name}.{extension}").is_err());
+        assert!(Layout::parse("{name").is_err());
+        assert!(Layout::parse("../{name}").is_err());
+        assert!(Layout::parse("/{name}").is_err());
+        assert!(Layout::parse("{bundle}/{ext}").is_err());
+    }
+}
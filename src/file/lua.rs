@@ -1,6 +1,6 @@
 use super::*;
 
-pub(crate) struct LuaParser;
+pub struct LuaParser;
 
 impl Extractor for LuaParser {
     fn extract(
@@ -33,8 +33,7 @@ impl Extractor for LuaParser {
         leb128::write::unsigned(&mut *shared_flex, path_len).unwrap();
         shared_flex.write_u8(b'@').unwrap();
 
-        let slice;
-        (slice, _) = shared.split_at_mut(len);
+        let (slice, mut shared) = shared.split_at_mut(len);
         for b in slice.iter_mut() {
             let c = entry.read_u8().unwrap();
             *b = c;
@@ -44,7 +43,15 @@ impl Extractor for LuaParser {
 
         io::copy(&mut entry, &mut *shared_flex).unwrap();
 
-        options.out.write(lua_path.as_ref(), &shared_flex)?;
+        if options.layout.is_default() {
+            options.out.write(lua_path.as_ref(), &shared_flex)?;
+        } else {
+            // the chunkname is the name even for files missing from the dictionary
+            let mut vars = layout_vars(&mut shared, entry, options);
+            vars.name = lua_path.strip_suffix(".lua").unwrap_or(lua_path);
+            let path = layout_path(&mut shared, options.layout, &vars);
+            options.out.write(path, &shared_flex)?;
+        }
 
         Ok(shared_flex.len() as u64)
     }
@@ -1,6 +1,6 @@
 use super::*;
 
-pub(crate) struct MaterialParser;
+pub struct MaterialParser;
 
 impl Extractor for MaterialParser {
     fn extract(
@@ -6,23 +6,37 @@ use std::io::Write;
 use std::mem;
 use std::path::Component;
 use std::path::Path;
+use std::sync::Mutex;
+use crate::bundle::ChunkPool;
 use crate::bundle::Entry;
-use crate::oodle::Oodle;
+use crate::decompress::Decompressor;
+use crate::duplicates::Conflicts;
+use crate::duplicates::Owners;
 use crate::hash::MurmurHash;
 use crate::hash::MurmurHash32;
-use crate::hash::FILE_EXTENSION;
+use crate::hash::extension_name;
 use byteorder::ReadBytesExt;
 use byteorder::WriteBytesExt;
 use byteorder::LE;
 use crate::scoped_fs::ScopedFs;
 
 mod bones;
+mod layout;
 mod lua;
 mod material;
 mod package;
 mod strings;
 mod texture;
 
+pub use bones::BonesParser;
+pub use layout::Layout;
+pub use layout::LayoutVars;
+pub use lua::LuaParser;
+pub use material::MaterialParser;
+pub use package::PackageParser;
+pub use strings::StringsParser;
+pub use texture::TextureParser;
+
 macro_rules! write_help {
     ($dst:expr, $($arg:tt)*) => {{
         let s = crate::file::write_slice($dst, format_args!($($arg)*)).unwrap();
@@ -31,7 +45,11 @@ macro_rules! write_help {
 }
 pub(crate) use write_help;
 
-trait Extractor {
+/// Converts a bundle [`Entry`] into files written to [`ExtractOptions::out`].
+pub trait Extractor {
+    /// Extract `entry` to `file_path`, returning the number of bytes written.
+    ///
+    /// `shared` and `shared2` are scratch buffers from a [`Pool`].
     fn extract(
         &self,
         entry: &mut Entry<'_, '_>,
@@ -42,31 +60,82 @@ trait Extractor {
     ) -> io::Result<u64>;
 }
 
-pub(crate) struct ExtractOptions<'a> {
-    pub(crate) target: &'a Path,
-    pub(crate) out: ScopedFs,
-    pub(crate) oodle: &'a Oodle,
-    pub(crate) dictionary: &'a HashMap<MurmurHash, &'a str>,
-    pub(crate) dictionary_short: &'a HashMap<MurmurHash32, &'a str>,
-    pub(crate) skip_extract: bool,
-    pub(crate) skip_unknown: bool,
-    pub(crate) as_blob: bool,
+pub struct ExtractOptions<'a> {
+    /// Directory of the bundle being extracted, used to find resource files
+    /// under `data/`.
+    pub target: &'a Path,
+    pub out: ScopedFs,
+    /// Paths of the extracted files under `out`.
+    pub layout: &'a Layout,
+    pub oodle: &'a dyn Decompressor,
+    pub dictionary: &'a HashMap<MurmurHash, &'a str>,
+    pub dictionary_short: &'a HashMap<MurmurHash32, &'a str>,
+    pub skip_extract: bool,
+    /// Skip files without a name in `dictionary`, except lua files and
+    /// strings files that are filtered for.
+    pub skip_unknown: bool,
+    /// Number of files skipped by `skip_unknown` per extension hash.
+    pub skipped_unknown: &'a Mutex<HashMap<u64, u64>>,
+    /// Extension hashes without a name that were found in bundle indexes and
+    /// in the file lists of extracted packages.
+    pub unknown_extensions: &'a Mutex<HashMap<u64, UnknownExtension>>,
+    /// Bundle each file is extracted from when it is in several bundles.
+    pub owners: Option<&'a Owners>,
+    /// Hash files in several bundles instead of extracting, see
+    /// [`crate::extract_bundle`].
+    pub conflicts: Option<&'a Mutex<Conflicts>>,
+    /// Always write files raw instead of using an [`Extractor`].
+    pub as_blob: bool,
+    /// Threads that decompress chunks ahead of the read position.
+    pub chunk_pool: Option<&'a ChunkPool>,
+    /// Chunks decompressed in parallel on `chunk_pool`, see
+    /// [`crate::BundleFd::set_prefetch`].
+    pub prefetch: usize,
+}
+
+/// Files with an extension hash that has no name.
+#[derive(Debug, Default)]
+pub struct UnknownExtension {
+    pub count: u64,
+    /// The bundles with the lowest hashes the extension was found in, up to
+    /// [`UnknownExtension::MAX_BUNDLES`].
+    pub bundles: Vec<u64>,
+}
+
+impl UnknownExtension {
+    pub const MAX_BUNDLES: usize = 3;
+
+    pub fn add(&mut self, count: u64, bundle: Option<u64>) {
+        self.count += count;
+        if let Some(bundle) = bundle {
+            if let Err(i) = self.bundles.binary_search(&bundle) {
+                self.bundles.insert(i, bundle);
+                self.bundles.truncate(Self::MAX_BUNDLES);
+            }
+        }
+    }
 }
 
-pub(crate) fn extract(
+/// Get the [`Extractor`] for an extension hash.
+pub fn extractor_for(ext: u64) -> Option<&'static dyn Extractor> {
+    Some(match ext {
+        0x18dead01056b72e9 => &BonesParser,
+        0xa14e8dfa2cd117e2 => &LuaParser,
+        0xeac0b497876adedf => &MaterialParser,
+        0xad9c6d9ed1e5e77a => &PackageParser,
+        0x0d972bab10b40fd3 => &StringsParser,
+        0xcd4238c6a0c69e32 => &TextureParser,
+        _ => return None,
+    })
+}
+
+/// Extract `entry` with its [`Extractor`] or write it raw if there is none.
+pub fn extract(
     mut entry: Entry<'_, '_>,
     pool: &mut Pool,
     options: &ExtractOptions<'_>,
 ) -> io::Result<u64> {
-    let extractor: Option<&'static dyn Extractor> = 'res: {Some(match entry.ext {
-        0x18dead01056b72e9 => &bones::BonesParser,
-        0xa14e8dfa2cd117e2 => &lua::LuaParser,
-        0xeac0b497876adedf => &material::MaterialParser,
-        0xad9c6d9ed1e5e77a => &package::PackageParser,
-        0x0d972bab10b40fd3 => &strings::StringsParser,
-        0xcd4238c6a0c69e32 => &texture::TextureParser,
-        _ => break 'res None,
-    })};
+    let extractor = extractor_for(entry.ext);
 
     let Pool {
         shared,
@@ -77,18 +146,10 @@ pub(crate) fn extract(
     }
     let mut shared = &mut shared[..];
 
-    let file_name = match options.dictionary.get(&MurmurHash::from(entry.name)) {
-        Some(s) => s,
-        None => write_help!(&mut shared, "{:016x}", entry.name),
-    };
-
-    let ext_name = match FILE_EXTENSION.binary_search_by(|probe| probe.0.cmp(&entry.ext)) {
-        Ok(i) => FILE_EXTENSION[i].1,
-        Err(_) => write_help!(&mut shared, "{:016x}", entry.ext),
-    };
+    let vars = layout_vars(&mut shared, &entry, options);
 
     if options.as_blob || extractor.is_none() {
-        let out = path_concat(&Path::new("."), &mut shared, file_name, Some(ext_name));
+        let out = layout_path(&mut shared, options.layout, &vars);
 
         shared2.clear();
         shared2.reserve(0x1000);
@@ -110,7 +171,7 @@ pub(crate) fn extract(
         io::copy(&mut &shared2[..], &mut fd).unwrap();
         io::copy(&mut entry, &mut fd).map(|copied| copied + shared2.len() as u64)
     } else {
-        let out = path_concat(&Path::new("."), &mut shared, file_name, Some(ext_name));
+        let out = layout_path(&mut shared, options.layout, &vars);
 
         let extractor = extractor.unwrap();
         extractor.extract(&mut entry, out, shared, shared2, options)
@@ -118,13 +179,13 @@ pub(crate) fn extract(
 }
 
 // second shared buffer is necessary for resizing after slices have been made
-pub(crate) struct Pool {
+pub struct Pool {
     shared: Vec<u8>,
     shared2: Vec<u8>,
 }
 
 impl Pool {
-    pub(crate) fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             shared: Vec::new(),
             shared2: Vec::new(),
@@ -132,6 +193,12 @@ impl Pool {
     }
 }
 
+impl Default for Pool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 fn split_vec<'a, const N: usize>(
     buffer: &'a mut Vec<u8>,
     parts: [usize; N],
@@ -199,6 +266,41 @@ fn path_concat<'a>(
     path
 }
 
+// placeholders of ExtractOptions::layout for `entry`
+fn layout_vars<'s>(
+    buffer: &mut &'s mut [u8],
+    entry: &Entry<'_, '_>,
+    options: &'s ExtractOptions<'_>,
+) -> LayoutVars<'s> {
+    let name = match options.dictionary.get(&MurmurHash::from(entry.name)) {
+        Some(s) => s,
+        None => write_help!(buffer, "{:016x}", entry.name),
+    };
+
+    // the dictionary can name extensions of new content types too
+    let ext = match extension_name(entry.ext)
+        .or_else(|| options.dictionary.get(&MurmurHash::from(entry.ext)).copied())
+    {
+        Some(s) => s,
+        None => write_help!(buffer, "{:016x}", entry.ext),
+    };
+
+    LayoutVars {
+        bundle: entry.bundle(),
+        ext,
+        name,
+        name_hash: entry.name,
+        lang: "",
+    }
+}
+
+// path of a file under ExtractOptions::out
+fn layout_path<'a>(buffer: &mut &'a mut [u8], layout: &Layout, vars: &LayoutVars<'_>) -> &'a Path {
+    let path = Path::new(write_help!(buffer, "./{}", layout.render(vars)));
+    assert!(no_escape(path), "{}", path.display());
+    path
+}
+
 fn data_path_from(buffer: &[u8]) -> Option<&str> {
     match std::ffi::CStr::from_bytes_until_nul(&buffer) {
         Ok(s) => s.to_str().ok(),
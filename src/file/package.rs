@@ -1,8 +1,8 @@
-use crate::hash::FILE_EXTENSION;
+use crate::hash::extension_name;
 use crate::hash::MurmurHash;
 use super::*;
 
-pub(crate) struct PackageParser;
+pub struct PackageParser;
 
 impl Extractor for PackageParser {
     fn extract(
@@ -20,14 +20,13 @@ impl Extractor for PackageParser {
         assert_eq!(43, entry.read_u32::<LE>().unwrap());
         let num_files = entry.read_u32::<LE>().unwrap();
 
+        let mut unknown = HashMap::new();
         write!(&mut shared_flex, "[").unwrap();
         for i in 0..num_files {
             let ext_hash = entry.read_u64::<LE>().unwrap();
             let name_hash = entry.read_u64::<LE>().unwrap();
-            let ext = FILE_EXTENSION
-                .binary_search_by(|(probe, _)| probe.cmp(&ext_hash))
-                .map(|i| FILE_EXTENSION[i].1)
-                .ok();
+            let ext = extension_name(ext_hash)
+                .or_else(|| options.dictionary.get(&MurmurHash(ext_hash)).copied());
             let name = options.dictionary.get(&MurmurHash(name_hash));
 
             if i > 0 {
@@ -40,10 +39,19 @@ impl Extractor for PackageParser {
             if let Some(ext) = ext {
                 write!(&mut shared_flex, "\"ext\":\"{ext}\"}}").unwrap();
             } else {
+                *unknown.entry(ext_hash).or_insert(0) += 1;
                 write!(&mut shared_flex, "{{\"ext_hash\":\"{ext_hash:016x}\",").unwrap();
             }
         }
         write!(&mut shared_flex, "]").unwrap();
+
+        if !unknown.is_empty() {
+            let mut total = options.unknown_extensions.lock().unwrap();
+            for (ext, count) in unknown {
+                total.entry(ext).or_default().add(count, entry.bundle());
+            }
+        }
+
         assert_eq!(1, entry.read_u8().unwrap());
         assert!(entry.read_u8().is_err());
 
@@ -60,7 +60,7 @@ impl fmt::Display for Language {
     }
 }
 
-pub(crate) struct StringsParser;
+pub struct StringsParser;
 
 impl Extractor for StringsParser {
     fn extract(
@@ -173,10 +173,19 @@ impl Extractor for StringsParser {
                 write_help!(&mut shared, "{kind:016x}")
             };
 
-            let stem = file_path.file_stem().unwrap().to_str().unwrap();
-            let file = write_help!(&mut shared, "{stem}.{lang}");
-            let parent = file_path.parent().unwrap();
-            let path = path_concat(parent, &mut shared, file, Some("json"));
+            let path = if options.layout.has_lang() {
+                let mut vars = layout_vars(&mut shared, entry, options);
+                vars.lang = lang;
+                let file_path = layout_path(&mut shared, options.layout, &vars);
+                let stem = file_path.file_stem().unwrap().to_str().unwrap();
+                let parent = file_path.parent().unwrap();
+                path_concat(parent, &mut shared, stem, Some("json"))
+            } else {
+                let stem = file_path.file_stem().unwrap().to_str().unwrap();
+                let file = write_help!(&mut shared, "{stem}.{lang}");
+                let parent = file_path.parent().unwrap();
+                path_concat(parent, &mut shared, file, Some("json"))
+            };
             options.out.write(path, &shared_flex)?;
 
             wrote += shared_flex.len() as u64;
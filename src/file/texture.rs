@@ -12,7 +12,7 @@ use super::*;
 
 const DDSD_MIPMAPCOUNT: u32 = 0x20000;
 
-pub(crate) struct TextureParser;
+pub struct TextureParser;
 
 impl Extractor for TextureParser {
     fn extract(
@@ -156,7 +156,7 @@ impl Extractor for TextureParser {
                 out_fd.write_all(&out_buf[..148]).unwrap();
                 148 + sort_write_texture_chunks(
                     memory_pool,
-                    &options.oodle,
+                    options.oodle,
                     &mut data_rdr,
                     &chunks[..num_chunks as usize],
                     chunk_width,
@@ -206,7 +206,7 @@ fn check_dxt10(mut dxt10: &[u8]) {
 // ```
 fn sort_write_texture_chunks(
     memory_pool: &mut Vec<u8>,
-    oodle: &Oodle,
+    oodle: &dyn Decompressor,
     data_rdr: &mut ChunkReader<File>,
     chunks: &[u32],
     chunk_width: u32,
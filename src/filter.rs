@@ -0,0 +1,350 @@
+//! Filters selecting which files of a bundle are extracted.
+//!
+//! Files are selected by extension hash and by patterns matched against
+//! `name.ext`, the path a file is extracted to. Names and extensions come from
+//! the dictionary and fall back to their hash as 16 hex digits.
+//!
+//! Glob patterns match the whole path. `*` matches any part of a directory or
+//! file name, `**` matches across directories, `**/` matches any number of
+//! directories including none and `?` matches a single character other than
+//! `/`. So `scripts/ui/**` selects everything under `scripts/ui` and
+//! `content/**/*.material` every material under `content`.
+//!
+//! A name list selects exactly the files listed. Every line is a name, which
+//! selects the files with that name and any extension, or `ext:name` with
+//! both parts either a hash as 16 hex digits or a name that is hashed.
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::io;
+
+use crate::hash::MurmurHash;
+use crate::hash::extension_name;
+use crate::hash::murmur_hash64a;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(String),
+    // ?
+    Char,
+    // *
+    Name,
+    // **
+    Any,
+    // **/
+    Dirs,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob {
+    tokens: Vec<Token>,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Self {
+        let mut tokens = Vec::new();
+        let mut literal = String::new();
+        let mut rest = pattern;
+        while let Some(c) = rest.chars().next() {
+            let (token, len) = if rest.starts_with("**/") {
+                (Token::Dirs, 3)
+            } else if rest.starts_with("**") {
+                (Token::Any, 2)
+            } else if c == '*' {
+                (Token::Name, 1)
+            } else if c == '?' {
+                (Token::Char, 1)
+            } else {
+                literal.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            };
+            if !literal.is_empty() {
+                tokens.push(Token::Literal(std::mem::take(&mut literal)));
+            }
+            tokens.push(token);
+            rest = &rest[len..];
+        }
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(literal));
+        }
+        Self {
+            tokens,
+        }
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        match_tokens(&self.tokens, path)
+    }
+}
+
+fn match_tokens(tokens: &[Token], path: &str) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return path.is_empty();
+    };
+    match token {
+        Token::Literal(s) => path.strip_prefix(s.as_str())
+            .is_some_and(|path| match_tokens(rest, path)),
+        Token::Char => path.chars().next()
+            .filter(|c| *c != '/')
+            .is_some_and(|c| match_tokens(rest, &path[c.len_utf8()..])),
+        Token::Name => {
+            let end = path.find('/').unwrap_or(path.len());
+            path[..end].char_indices()
+                .map(|(i, _)| i)
+                .chain([end])
+                .any(|i| match_tokens(rest, &path[i..]))
+        }
+        Token::Any => path.char_indices()
+            .map(|(i, _)| i)
+            .chain([path.len()])
+            .any(|i| match_tokens(rest, &path[i..])),
+        Token::Dirs => match_tokens(rest, path)
+            || path.match_indices('/').any(|(i, _)| match_tokens(rest, &path[i + 1..])),
+    }
+}
+
+pub enum Pattern {
+    Glob(Glob),
+    /// Matches anywhere in the path unless anchored with `^` and `$`.
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl Pattern {
+    pub fn glob(pattern: &str) -> Self {
+        Self::Glob(Glob::new(pattern))
+    }
+
+    #[cfg(feature = "regex")]
+    pub fn regex(pattern: &str) -> io::Result<Self> {
+        regex::Regex::new(pattern)
+            .map(Self::Regex)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        match self {
+            Self::Glob(glob) => glob.is_match(path),
+            #[cfg(feature = "regex")]
+            Self::Regex(regex) => regex.is_match(path),
+        }
+    }
+}
+
+#[derive(Default)]
+struct NameList {
+    // names listed without an extension
+    names: HashSet<u64>,
+    files: HashSet<(u64, u64)>,
+}
+
+/// Extension, path and name list filters. A file is selected when its
+/// extension is one of the included extensions, or any extension if none are,
+/// and is not excluded, and its path matches an include pattern, if there are
+/// any, and no exclude pattern, and it is in the name list if one was added.
+#[derive(Default)]
+pub struct Filter {
+    extensions: Vec<u64>,
+    excluded_extensions: Vec<u64>,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    list: Option<NameList>,
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn include_ext(&mut self, ext: u64) {
+        self.extensions.push(ext);
+    }
+
+    pub fn exclude_ext(&mut self, ext: u64) {
+        self.excluded_extensions.push(ext);
+    }
+
+    pub fn include(&mut self, pattern: Pattern) {
+        self.include.push(pattern);
+    }
+
+    pub fn exclude(&mut self, pattern: Pattern) {
+        self.exclude.push(pattern);
+    }
+
+    /// Add the names and `ext:name` pairs of a name list, one per line.
+    /// Returns the number of entries added.
+    pub fn add_name_list(&mut self, data: &str) -> io::Result<usize> {
+        let list = self.list.get_or_insert_with(NameList::default);
+        let mut added = 0;
+        for (i, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData,
+                format!("line {}: {line:?} is not a name or ext:name pair", i + 1));
+            let inserted = match line.split_once(':') {
+                Some((ext, name)) => {
+                    if ext.is_empty() || name.is_empty() {
+                        return Err(invalid());
+                    }
+                    list.files.insert((list_hash(ext), list_hash(name)))
+                }
+                None => list.names.insert(list_hash(line)),
+            };
+            added += inserted as usize;
+        }
+        Ok(added)
+    }
+
+    /// True if nothing was added, in which case every file is selected.
+    pub fn is_empty(&self) -> bool {
+        self.extensions.is_empty()
+            && self.excluded_extensions.is_empty()
+            && self.include.is_empty()
+            && self.exclude.is_empty()
+            && self.list.is_none()
+    }
+
+    /// True if `ext` was explicitly included.
+    pub fn includes_ext(&self, ext: u64) -> bool {
+        self.extensions.contains(&ext)
+    }
+
+    pub fn matches_ext(&self, ext: u64) -> bool {
+        (self.extensions.is_empty() || self.extensions.contains(&ext))
+            && !self.excluded_extensions.contains(&ext)
+    }
+
+    /// Check the file `(ext, name)` with names from `dictionary`.
+    pub fn matches(&self, ext: u64, name: u64, dictionary: &HashMap<MurmurHash, &str>) -> bool {
+        if !self.matches_ext(ext) {
+            return false;
+        }
+        if let Some(list) = &self.list {
+            if !list.names.contains(&name) && !list.files.contains(&(ext, name)) {
+                return false;
+            }
+        }
+        if self.include.is_empty() && self.exclude.is_empty() {
+            return true;
+        }
+
+        let mut path = String::new();
+        match dictionary.get(&MurmurHash::from(name)) {
+            Some(name) => path.push_str(name),
+            None => write!(path, "{name:016x}").unwrap(),
+        }
+        path.push('.');
+        match extension_name(ext).or_else(|| dictionary.get(&MurmurHash::from(ext)).copied()) {
+            Some(ext) => path.push_str(ext),
+            None => write!(path, "{ext:016x}").unwrap(),
+        }
+
+        (self.include.is_empty() || self.include.iter().any(|p| p.is_match(&path)))
+            && !self.exclude.iter().any(|p| p.is_match(&path))
+    }
+
+    /// Entries of the name list without a file in `seen`, as `(ext, name)`
+    /// with no `ext` for names listed without one.
+    pub fn missing<'a>(
+        &self,
+        seen: impl IntoIterator<Item = &'a (u64, u64)>,
+    ) -> Vec<(Option<u64>, u64)> {
+        let Some(list) = &self.list else {
+            return Vec::new();
+        };
+        let mut names = list.names.clone();
+        let mut files = list.files.clone();
+        for &(ext, name) in seen {
+            names.remove(&name);
+            files.remove(&(ext, name));
+        }
+        let mut missing = names.into_iter()
+            .map(|name| (None, name))
+            .chain(files.into_iter().map(|(ext, name)| (Some(ext), name)))
+            .collect::<Vec<_>>();
+        missing.sort_unstable();
+        missing
+    }
+}
+
+// 16 hex digits are a hash, anything else is hashed
+fn list_hash(s: &str) -> u64 {
+    match u64::from_str_radix(s, 16) {
+        Ok(hash) if s.len() == 16 => hash,
+        _ => murmur_hash64a(s.as_bytes(), 0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn glob() {
+        let glob = Glob::new("scripts/ui/**");
+        assert!(glob.is_match("scripts/ui/views/menu.lua"));
+        assert!(!glob.is_match("scripts/settings/ui.lua"));
+
+        let glob = Glob::new("content/ui/materials/*.material");
+        assert!(glob.is_match("content/ui/materials/frame.material"));
+        assert!(!glob.is_match("content/ui/materials/icons/frame.material"));
+
+        let glob = Glob::new("content/**/?_*.material");
+        assert!(glob.is_match("content/a_b.material"));
+        assert!(glob.is_match("content/ui/x/a_.material"));
+        assert!(!glob.is_match("content/ui/ab_.material"));
+    }
+
+    #[test]
+    fn filter() {
+        let lua = murmur_hash64a(b"lua", 0);
+        let texture = murmur_hash64a(b"texture", 0);
+        let name = murmur_hash64a(b"scripts/ui/menu", 0);
+        let dictionary = HashMap::from([(MurmurHash::from(name), "scripts/ui/menu")]);
+
+        let mut filter = Filter::new();
+        assert!(filter.is_empty());
+        filter.exclude_ext(texture);
+        assert!(filter.matches(lua, name, &dictionary));
+        assert!(!filter.matches(texture, name, &dictionary));
+
+        filter.include(Pattern::glob("scripts/ui/**"));
+        filter.exclude(Pattern::glob("**/*_test.lua"));
+        assert!(filter.matches(lua, name, &dictionary));
+        assert!(!filter.matches(lua, 1, &dictionary));
+        filter.include(Pattern::glob("0000000000000001.*"));
+        assert!(filter.matches(lua, 1, &dictionary));
+        filter.exclude(Pattern::glob("*/ui/menu.lua"));
+        assert!(!filter.matches(lua, name, &dictionary));
+    }
+    #[test]
+    fn name_list() {
+        let lua = murmur_hash64a(b"lua", 0);
+        let texture = murmur_hash64a(b"texture", 0);
+        let menu = murmur_hash64a(b"scripts/ui/menu", 0);
+        let dictionary = HashMap::new();
+
+        let mut filter = Filter::new();
+        let list = format!("scripts/ui/menu\r\n\n{texture:016x}:content/ui/icon\nlua:0000000000000002\n");
+        assert_eq!(3, filter.add_name_list(&list).unwrap());
+        assert!(filter.add_name_list("lua:").is_err());
+        assert!(!filter.is_empty());
+        assert!(filter.matches(lua, menu, &dictionary));
+        assert!(filter.matches(texture, menu, &dictionary));
+        let icon = murmur_hash64a(b"content/ui/icon", 0);
+        assert!(filter.matches(texture, icon, &dictionary));
+        assert!(filter.matches(lua, 2, &dictionary));
+        assert!(!filter.matches(texture, 2, &dictionary));
+
+        assert_eq!(vec![(Some(lua), 2)], filter.missing(&[(lua, menu), (texture, icon), (texture, 2)]));
+
+        // an empty list selects nothing
+        let mut filter = Filter::new();
+        filter.add_name_list("").unwrap();
+        assert!(!filter.matches(lua, menu, &dictionary));
+    }
+}
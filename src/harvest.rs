@@ -0,0 +1,475 @@
+//! Candidate names collected from bundle contents for `limn harvest`.
+//!
+//! Candidates come from the chunkname and string constants of lua files and
+//! from the `data/...` resource paths of materials and textures. They are
+//! matched against the name hashes of bundle entries and package contents and
+//! against the short hashes of strings keys.
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io;
+use std::io::Read;
+use std::str;
+
+use byteorder::ReadBytesExt;
+use byteorder::LE;
+
+use crate::bundle::BundleFd;
+use crate::bundle::Variant;
+use crate::decompress::Decompressor;
+use crate::dictionary::Dictionary;
+use crate::error::Error;
+use crate::error::Result;
+use crate::hash::murmur_hash64a;
+use crate::hash::extension_name;
+use crate::hashes::HashesBin;
+
+const LUA: u64 = murmur_hash64a(b"lua", 0);
+const MATERIAL: u64 = murmur_hash64a(b"material", 0);
+const PACKAGE: u64 = murmur_hash64a(b"package", 0);
+const STRINGS: u64 = murmur_hash64a(b"strings", 0);
+const TEXTURE: u64 = murmur_hash64a(b"texture", 0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Source {
+    LuaChunkName,
+    LuaConstant,
+    DataPath,
+}
+
+impl Source {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::LuaChunkName => "lua chunkname",
+            Self::LuaConstant => "lua constant",
+            Self::DataPath => "data path",
+        }
+    }
+}
+
+/// File a candidate was found in. Of several the smallest is kept so the
+/// result does not depend on the order bundles are scanned in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Provenance {
+    pub source: Source,
+    pub bundle: Option<u64>,
+    pub ext: u64,
+    pub name: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Match {
+    /// The hash is the name of a file.
+    Name,
+    /// The upper 32 bits of the hash are a strings key.
+    Short,
+}
+
+/// Hashes candidates are kept for.
+#[derive(Debug, Default)]
+pub struct Targets {
+    names: HashSet<u64>,
+    short: HashSet<u32>,
+}
+
+impl Targets {
+    pub fn insert(&mut self, name: u64) {
+        self.names.insert(name);
+    }
+
+    pub fn insert_short(&mut self, short: u32) {
+        self.short.insert(short);
+    }
+
+    /// Add the name hashes of a `hashes.bin` written by `limn --dump-hashes`.
+    pub fn read_hashes_bin(&mut self, data: &[u8]) -> io::Result<()> {
+        let hashes = HashesBin::parse(data)?;
+        self.names.extend(hashes.pairs().iter().map(|(_, name)| name));
+        Ok(())
+    }
+
+    /// Add hashes from a list of hex hashes, one per line. Hashes with 8
+    /// digits are short hashes.
+    pub fn read_hex_list(&mut self, data: &str) -> io::Result<()> {
+        for (i, line) in data.lines().enumerate() {
+            let hex = line.trim();
+            let hex = hex.strip_prefix("0x").unwrap_or(hex);
+            let parsed = match hex.len() {
+                0 => continue,
+                8 => u32::from_str_radix(hex, 16).map(|hash| self.insert_short(hash)).ok(),
+                16 => u64::from_str_radix(hex, 16).map(|hash| self.insert(hash)).ok(),
+                _ => None,
+            };
+            if parsed.is_none() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                    format!("line {}: invalid hash {line:?}", i + 1)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the hashes of names already in `dictionary`.
+    pub fn remove_known(&mut self, dictionary: &Dictionary) {
+        for (hash, _) in dictionary.iter() {
+            self.names.remove(&hash);
+            self.short.remove(&((hash >> 32) as u32));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len() + self.short.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn find(&self, name: &str) -> Option<Match> {
+        let hash = murmur_hash64a(name.as_bytes(), 0);
+        if self.names.contains(&hash) {
+            Some(Match::Name)
+        } else if self.short.contains(&((hash >> 32) as u32)) {
+            Some(Match::Short)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Harvest {
+    candidates: HashMap<String, Provenance>,
+    pub targets: Targets,
+}
+
+impl Harvest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn num_candidates(&self) -> usize {
+        self.candidates.len()
+    }
+
+    /// Collect candidates and targets from every file in `bundle`.
+    pub fn bundle(
+        &mut self,
+        bundle: &mut BundleFd<'_>,
+        oodle: &dyn Decompressor,
+        scratch: &mut Vec<u8>,
+        buf: &mut Vec<u8>,
+    ) -> Result<()> {
+        let bundle_hash = bundle.name;
+        for entry in bundle.index()? {
+            self.targets.insert(entry.name);
+        }
+
+        let mut files = bundle.files(oodle, scratch)?;
+        while let Some(mut entry) = files.next_file()? {
+            if !matches!(entry.ext, LUA | MATERIAL | PACKAGE | STRINGS | TEXTURE) {
+                continue;
+            }
+
+            buf.clear();
+            entry.read_to_end(buf).map_err(|e| Error::from_io(bundle_hash, 0, e))?;
+            let provenance = |source| Provenance {
+                source,
+                bundle: bundle_hash,
+                ext: entry.ext,
+                name: entry.name,
+            };
+            // files that cannot be parsed are skipped, they are checked when extracting
+            match entry.ext {
+                LUA => {
+                    let _ = lua_strings(buf, |s, source| self.add(s, provenance(source)));
+                }
+                MATERIAL => self.add(buf, provenance(Source::DataPath)),
+                TEXTURE => {
+                    if let Some(path) = texture_data_path(entry.variants(), buf) {
+                        self.add(path, provenance(Source::DataPath));
+                    }
+                }
+                PACKAGE => {
+                    let _ = self.package(buf);
+                }
+                STRINGS => {
+                    let _ = self.strings(entry.variants(), buf);
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add the candidates and targets of `other`.
+    pub fn merge(&mut self, other: Harvest) {
+        for (name, provenance) in other.candidates {
+            self.insert(&name, provenance);
+        }
+        self.targets.names.extend(other.targets.names);
+        self.targets.short.extend(other.targets.short);
+    }
+
+    /// Candidates matching a target that are not in `dictionary`, sorted by
+    /// name.
+    pub fn new_names(&self, dictionary: &Dictionary) -> Vec<(&str, Match, Provenance)> {
+        let mut names = self.candidates.iter()
+            .filter(|(name, _)| dictionary.get(murmur_hash64a(name.as_bytes(), 0)).is_none())
+            .filter_map(|(name, &provenance)| {
+                self.targets.find(name).map(|m| (name.as_str(), m, provenance))
+            })
+            .collect::<Vec<_>>();
+        names.sort_by(|a, b| a.0.cmp(b.0));
+        names
+    }
+
+    fn add(&mut self, s: &[u8], provenance: Provenance) {
+        // resource paths are padded with NUL
+        let s = s.split(|b| *b == 0).next().unwrap();
+        let Ok(s) = str::from_utf8(s) else {
+            return;
+        };
+        let s = s.trim_start_matches('@');
+        if s.is_empty() || s.len() > 512 || s.chars().any(char::is_control) {
+            return;
+        }
+
+        self.insert(s, provenance);
+        // names in bundles have no extension
+        if let Some((stem, ext)) = s.rsplit_once('.') {
+            if extension_name(murmur_hash64a(ext.as_bytes(), 0)) == Some(ext) {
+                self.insert(stem, provenance);
+            }
+        }
+    }
+
+    fn insert(&mut self, name: &str, provenance: Provenance) {
+        match self.candidates.get_mut(name) {
+            Some(kept) => *kept = provenance.min(*kept),
+            None => {
+                self.candidates.insert(name.to_string(), provenance);
+            }
+        }
+    }
+
+    fn package(&mut self, mut data: &[u8]) -> io::Result<()> {
+        let _version = data.read_u32::<LE>()?;
+        let num_files = data.read_u32::<LE>()?;
+        for _ in 0..num_files {
+            let _ext = data.read_u64::<LE>()?;
+            self.targets.insert(data.read_u64::<LE>()?);
+        }
+        Ok(())
+    }
+
+    fn strings(&mut self, variants: &[Variant], mut data: &[u8]) -> io::Result<()> {
+        for variant in variants {
+            let size = (variant.body_size + variant.tail_size) as usize;
+            let mut body = data.get(..size).ok_or(io::ErrorKind::UnexpectedEof)?;
+            data = &data[size..];
+
+            let _unknown = body.read_u32::<LE>()?;
+            let num_items = body.read_u32::<LE>()?;
+            for _ in 0..num_items {
+                self.targets.insert_short(body.read_u32::<LE>()?);
+                let _offset = body.read_u32::<LE>()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// the resource path is the body of streamed textures and the tail of
+// textures with high resolution mipmaps
+fn texture_data_path<'d>(variants: &[Variant], data: &'d [u8]) -> Option<&'d [u8]> {
+    let prime = variants.first()?;
+    match prime.unknown1 {
+        1 => data.get(..prime.body_size as usize),
+        0 if prime.tail_size > 0 => data.get(data.len().checked_sub(prime.tail_size as usize)?..),
+        _ => None,
+    }
+}
+
+const LJ_FLAG_STRIP: u64 = 0x02;
+
+const KGC_CHILD: u64 = 0;
+const KGC_TAB: u64 = 1;
+const KGC_I64: u64 = 2;
+const KGC_U64: u64 = 3;
+const KGC_COMPLEX: u64 = 4;
+const KGC_STR: u64 = 5;
+
+const KTAB_INT: u64 = 3;
+const KTAB_NUM: u64 = 4;
+const KTAB_STR: u64 = 5;
+
+/// Call `f` with the chunkname and every string constant of a lua file as it
+/// is stored in a bundle. Returns `None` if the bytecode could not be parsed.
+pub fn lua_strings(data: &[u8], mut f: impl FnMut(&[u8], Source)) -> Option<()> {
+    let mut rdr = data.get(12..)?;
+    let header = rdr.read_u32::<LE>().ok()?;
+    if header != 38423579 && header != 2186495515 {
+        return None;
+    }
+
+    let flags = uleb(&mut rdr)?;
+    let strip = flags & LJ_FLAG_STRIP != 0;
+    if !strip {
+        let len = uleb(&mut rdr)?;
+        f(take(&mut rdr, len)?, Source::LuaChunkName);
+    }
+
+    loop {
+        let len = uleb(&mut rdr)?;
+        if len == 0 {
+            return Some(());
+        }
+        let proto = take(&mut rdr, len)?;
+        lua_proto_strings(proto, strip, &mut f)?;
+    }
+}
+
+fn lua_proto_strings(mut rdr: &[u8], strip: bool, f: &mut impl FnMut(&[u8], Source)) -> Option<()> {
+    // flags, number of parameters and frame size
+    take(&mut rdr, 3)?;
+    let num_uv = u64::from(rdr.read_u8().ok()?);
+    let num_kgc = uleb(&mut rdr)?;
+    let _num_kn = uleb(&mut rdr)?;
+    let num_bc = uleb(&mut rdr)?;
+    if !strip {
+        let debug_size = uleb(&mut rdr)?;
+        if debug_size > 0 {
+            let _first_line = uleb(&mut rdr)?;
+            let _num_lines = uleb(&mut rdr)?;
+        }
+    }
+    take(&mut rdr, num_bc.checked_mul(4)?)?;
+    take(&mut rdr, num_uv * 2)?;
+
+    // every constant takes at least one byte so malformed counts end at the
+    // end of the prototype
+    for _ in 0..num_kgc {
+        match uleb(&mut rdr)? {
+            KGC_CHILD => (),
+            KGC_TAB => {
+                let num_array = uleb(&mut rdr)?;
+                let num_hash = uleb(&mut rdr)?;
+                for _ in 0..num_array.checked_add(num_hash.checked_mul(2)?)? {
+                    match uleb(&mut rdr)? {
+                        KTAB_INT => {
+                            uleb(&mut rdr)?;
+                        }
+                        KTAB_NUM => {
+                            uleb(&mut rdr)?;
+                            uleb(&mut rdr)?;
+                        }
+                        kind if kind >= KTAB_STR => f(take(&mut rdr, kind - KTAB_STR)?, Source::LuaConstant),
+                        // nil, false and true
+                        _ => (),
+                    }
+                }
+            }
+            KGC_I64 | KGC_U64 => {
+                uleb(&mut rdr)?;
+                uleb(&mut rdr)?;
+            }
+            KGC_COMPLEX => {
+                for _ in 0..4 {
+                    uleb(&mut rdr)?;
+                }
+            }
+            kind => f(take(&mut rdr, kind - KGC_STR)?, Source::LuaConstant),
+        }
+    }
+    Some(())
+}
+
+fn uleb(rdr: &mut &[u8]) -> Option<u64> {
+    leb128::read::unsigned(rdr).ok()
+}
+
+fn take<'d>(rdr: &mut &'d [u8], len: u64) -> Option<&'d [u8]> {
+    let len = usize::try_from(len).ok()?;
+    if rdr.len() < len {
+        return None;
+    }
+    let (taken, rest) = rdr.split_at(len);
+    *rdr = rest;
+    Some(taken)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::io::Write;
+    use byteorder::WriteBytesExt;
+    use super::*;
+    use crate::decompress::Stored;
+    use crate::writer::BundleWriter;
+
+    fn lua() -> Vec<u8> {
+        let mut proto = vec![0, 0, 0, 0];
+        // 2 gc constants, no numbers, 1 instruction and no debug info
+        proto.extend_from_slice(&[2, 0, 1, 0]);
+        proto.extend_from_slice(&[0; 4]);
+        proto.push((KGC_STR + 9) as u8);
+        proto.extend_from_slice(b"scripts/b");
+        // table with an array part of one string
+        proto.extend_from_slice(&[KGC_TAB as u8, 1, 0, (KTAB_STR + 5) as u8]);
+        proto.extend_from_slice(b"loc_c");
+
+        let mut lua = vec![0; 12];
+        lua.write_u32::<LE>(38423579).unwrap();
+        lua.push(0);
+        lua.push(14);
+        lua.extend_from_slice(b"@scripts/a.lua");
+        lua.push(proto.len() as u8);
+        lua.extend_from_slice(&proto);
+        lua.push(0);
+        lua
+    }
+
+    #[test]
+    fn harvest() {
+        let mut strings = Vec::new();
+        strings.write_u32::<LE>(0).unwrap();
+        strings.write_u32::<LE>(1).unwrap();
+        strings.write_u32::<LE>((murmur_hash64a(b"loc_c", 0) >> 32) as u32).unwrap();
+        strings.write_u32::<LE>(16).unwrap();
+        strings.write_all(b"c\0").unwrap();
+
+        let mut package = Vec::new();
+        package.write_u32::<LE>(43).unwrap();
+        package.write_u32::<LE>(1).unwrap();
+        package.write_u64::<LE>(LUA).unwrap();
+        package.write_u64::<LE>(murmur_hash64a(b"scripts/b", 0)).unwrap();
+        package.push(1);
+
+        let mut writer = BundleWriter::new(8);
+        writer.add_file(LUA, murmur_hash64a(b"scripts/a", 0), 0)
+            .variant(0, 0, &lua(), b"");
+        writer.add_file(PACKAGE, 1, 0)
+            .variant(0, 0, &package, b"");
+        writer.add_file(STRINGS, 2, 0)
+            .variant(0, 0, &strings, b"");
+        writer.add_file(MATERIAL, 3, 0)
+            .variant(0, 0, b"data/12/34\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0", b"");
+        let mut bundle = Vec::new();
+        writer.write(&mut bundle).unwrap();
+
+        let mut rdr = Cursor::new(&bundle[..]);
+        let mut fd = BundleFd::new(Some(9), &mut rdr).unwrap();
+        let mut harvest = Harvest::new();
+        harvest.bundle(&mut fd, &Stored, &mut Vec::new(), &mut Vec::new()).unwrap();
+        // scripts/a.lua, scripts/a, scripts/b, loc_c and data/12/34
+        assert_eq!(5, harvest.num_candidates());
+
+        let provenance = |source, ext, name| Provenance { source, bundle: Some(9), ext, name };
+        let mut dictionary = Dictionary::new();
+        dictionary.insert("scripts/b", None);
+        assert_eq!(vec![
+            ("loc_c", Match::Short, provenance(Source::LuaConstant, LUA, murmur_hash64a(b"scripts/a", 0))),
+            ("scripts/a", Match::Name, provenance(Source::LuaChunkName, LUA, murmur_hash64a(b"scripts/a", 0))),
+        ], harvest.new_names(&dictionary));
+    }
+}
@@ -1,8 +1,10 @@
+use std::fmt;
 use std::sync::LazyLock;
+use std::sync::RwLock;
 use std::hash::Hash;
 use std::hash::Hasher;
 
-pub(crate) static FILE_EXTENSION: LazyLock<[(u64, &'static str); 50]> = LazyLock::new(|| {
+pub static FILE_EXTENSION: LazyLock<[(u64, &'static str); 50]> = LazyLock::new(|| {
     let mut a = [
         "animation",
         "animation_curves",
@@ -59,6 +61,38 @@ pub(crate) static FILE_EXTENSION: LazyLock<[(u64, &'static str); 50]> = LazyLock
     a
 });
 
+// sorted like FILE_EXTENSION, names are leaked since they are kept until exit
+static ADDED_EXTENSION: RwLock<Vec<(u64, &'static str)>> = RwLock::new(Vec::new());
+
+/// Name of the extension with hash `ext` from [`FILE_EXTENSION`] or the
+/// extensions added with [`add_extension`].
+pub fn extension_name(ext: u64) -> Option<&'static str> {
+    if let Ok(i) = FILE_EXTENSION.binary_search_by(|(probe, _)| probe.cmp(&ext)) {
+        return Some(FILE_EXTENSION[i].1);
+    }
+    let added = ADDED_EXTENSION.read().unwrap();
+    added.binary_search_by(|(probe, _)| probe.cmp(&ext))
+        .map(|i| added[i].1)
+        .ok()
+}
+
+/// Add an extension for [`extension_name`], such as one of a new content
+/// type. Returns false if it was already known.
+pub fn add_extension(name: &str) -> bool {
+    let ext = murmurhash64(name.as_bytes());
+    if extension_name(ext).is_some() {
+        return false;
+    }
+    let mut added = ADDED_EXTENSION.write().unwrap();
+    match added.binary_search_by(|(probe, _)| probe.cmp(&ext)) {
+        Ok(_) => false,
+        Err(i) => {
+            added.insert(i, (ext, Box::leak(name.into())));
+            true
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct MurmurHash(pub(crate) u64);
 
@@ -84,6 +118,12 @@ impl From<u64> for MurmurHash {
     }
 }
 
+impl fmt::LowerHex for MurmurHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct MurmurHash32(pub(crate) u32);
 
@@ -99,6 +139,12 @@ impl From<u32> for MurmurHash32 {
     }
 }
 
+impl fmt::LowerHex for MurmurHash32 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
 pub(crate) const fn murmurhash64(key: &[u8]) -> u64 {
     murmur_hash64a(key, 0)
 }
@@ -192,4 +238,23 @@ mod test {
             assert_eq!(*hash, murmur_hash64a(key, 0));
         }
     }
+
+    #[test]
+    fn added_extension() {
+        let ext = murmur_hash64a(b"limn_test_extension", 0);
+        assert_eq!(None, extension_name(ext));
+        assert!(add_extension("limn_test_extension"));
+        assert!(!add_extension("limn_test_extension"));
+        assert!(!add_extension("lua"));
+        assert_eq!(Some("limn_test_extension"), extension_name(ext));
+        assert_eq!(Some("lua"), extension_name(murmur_hash64a(b"lua", 0)));
+    }
+
+    #[test]
+    fn lower_hex() {
+        let hash = MurmurHash::new("test");
+        assert_eq!("2f4a8724618f4c63", format!("{hash:016x}"));
+        assert_eq!("2f4a8724", format!("{:08x}", hash.clone_short()));
+        assert_eq!("0000000000000001", format!("{:016x}", MurmurHash::from(1)));
+    }
 }
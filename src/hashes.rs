@@ -0,0 +1,151 @@
+//! The `hashes.bin` database written by `limn --dump-hashes`.
+//!
+//! `hashes.bin` is a flat list of `(ext, name)` pairs of little endian u64
+//! hashes sorted by extension and then name.
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HashesBin {
+    pairs: Vec<(u64, u64)>,
+}
+
+/// Pairs that differ between two [`HashesBin`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Diff {
+    pub added: Vec<(u64, u64)>,
+    pub removed: Vec<(u64, u64)>,
+}
+
+impl HashesBin {
+    /// Sort `pairs` and remove duplicates.
+    pub fn new(mut pairs: Vec<(u64, u64)>) -> Self {
+        pairs.sort_unstable();
+        pairs.dedup();
+        Self {
+            pairs,
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        if !data.len().is_multiple_of(16) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                "hashes.bin is not a list of extension and name hashes"));
+        }
+        let pairs = data.chunks_exact(16)
+            .map(|pair| (
+                u64::from_le_bytes(pair[..8].try_into().unwrap()),
+                u64::from_le_bytes(pair[8..].try_into().unwrap()),
+            ))
+            .collect();
+        Ok(Self::new(pairs))
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    pub fn write(&self, mut out: impl Write) -> io::Result<()> {
+        let mut bin = Vec::with_capacity(self.pairs.len() * 16);
+        for (ext, name) in &self.pairs {
+            bin.extend_from_slice(&ext.to_le_bytes());
+            bin.extend_from_slice(&name.to_le_bytes());
+        }
+        out.write_all(&bin)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// `(ext, name)` pairs in order.
+    pub fn pairs(&self) -> &[(u64, u64)] {
+        &self.pairs
+    }
+
+    /// Pairs with extension hash `ext`.
+    pub fn with_ext(&self, ext: u64) -> &[(u64, u64)] {
+        let start = self.pairs.partition_point(|(probe, _)| *probe < ext);
+        let end = self.pairs.partition_point(|(probe, _)| *probe <= ext);
+        &self.pairs[start..end]
+    }
+
+    /// Add the pairs of `other`.
+    pub fn merge(&mut self, other: &HashesBin) {
+        let mut pairs = Vec::with_capacity(self.pairs.len() + other.pairs.len());
+        let (mut a, mut b) = (&self.pairs[..], &other.pairs[..]);
+        while let (Some(x), Some(y)) = (a.first(), b.first()) {
+            pairs.push(*x.min(y));
+            if x <= y {
+                a = &a[1..];
+            }
+            if y <= x {
+                b = &b[1..];
+            }
+        }
+        pairs.extend_from_slice(a);
+        pairs.extend_from_slice(b);
+        self.pairs = pairs;
+    }
+
+    /// Pairs added and removed in `newer`.
+    pub fn diff(&self, newer: &HashesBin) -> Diff {
+        let mut diff = Diff::default();
+        let (mut a, mut b) = (&self.pairs[..], &newer.pairs[..]);
+        loop {
+            match (a.first(), b.first()) {
+                (Some(x), Some(y)) if x == y => {
+                    a = &a[1..];
+                    b = &b[1..];
+                }
+                (Some(x), Some(y)) if x < y => {
+                    diff.removed.push(*x);
+                    a = &a[1..];
+                }
+                (Some(x), None) => {
+                    diff.removed.push(*x);
+                    a = &a[1..];
+                }
+                (_, Some(y)) => {
+                    diff.added.push(*y);
+                    b = &b[1..];
+                }
+                (None, None) => return diff,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let old = HashesBin::new(vec![(2, 20), (1, 10), (2, 21), (1, 10)]);
+        assert_eq!(&[(1, 10), (2, 20), (2, 21)], old.pairs());
+        assert_eq!(&[(2, 20), (2, 21)], old.with_ext(2));
+
+        let mut bin = Vec::new();
+        old.write(&mut bin).unwrap();
+        assert_eq!(48, bin.len());
+        assert_eq!(old, HashesBin::parse(&bin).unwrap());
+        assert!(HashesBin::parse(&bin[1..]).is_err());
+
+        let new = HashesBin::new(vec![(1, 10), (2, 22), (3, 30)]);
+        assert_eq!(Diff {
+            added: vec![(2, 22), (3, 30)],
+            removed: vec![(2, 20), (2, 21)],
+        }, old.diff(&new));
+
+        let mut merged = old.clone();
+        merged.merge(&new);
+        assert_eq!(&[(1, 10), (2, 20), (2, 21), (2, 22), (3, 30)], merged.pairs());
+    }
+}
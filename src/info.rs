@@ -0,0 +1,266 @@
+//! Layout of a bundle as printed by `limn info`.
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+use crate::bundle::BundleFd;
+use crate::bundle::Variant;
+use crate::decompress::Decompressor;
+use crate::error::Error;
+use crate::error::Result;
+use crate::hash::extension_name;
+use crate::hash::MurmurHash;
+
+pub struct BundleInfo {
+    pub version: u8,
+    pub num_files: u32,
+    pub reserved: [u8; 256],
+    /// Compressed size of every chunk.
+    pub chunks: Vec<u32>,
+    /// Size of all files after decompression.
+    pub total_size: u64,
+    pub entries: Vec<EntryInfo>,
+}
+
+pub struct EntryInfo {
+    pub ext: u64,
+    pub name: u64,
+    pub mode: u32,
+    /// Offset of the entry header in the decompressed data.
+    pub offset: u64,
+    pub size: u64,
+    pub variants: Vec<Variant>,
+}
+
+impl BundleInfo {
+    /// Read the header, index and chunk table of `bundle` and the headers of
+    /// every file entry.
+    pub fn read(bundle: &mut BundleFd<'_>, oodle: &dyn Decompressor) -> Result<Self> {
+        let reserved = bundle.reserved()?;
+        let chunks = bundle.chunk_table()?;
+        let index = bundle.index()?.collect::<Vec<_>>();
+        if index.len() != bundle.num_files as usize {
+            return Err(Error::Truncated {
+                bundle: bundle.name,
+                offset: 12 + 256 + index.len() as u64 * 20,
+            });
+        }
+
+        let mut scratch = Vec::new();
+        let name = bundle.name;
+        let mut files = bundle.files(oodle, &mut scratch)?;
+        let offsets = files.entry_offsets()?.to_vec();
+        let mut entries = Vec::with_capacity(index.len());
+        for (index, offset) in index.into_iter().zip(offsets) {
+            if index.ext != offset.ext || index.name != offset.name {
+                return Err(Error::UnexpectedValue {
+                    bundle: name,
+                    offset: offset.offset,
+                    field: "entry name",
+                    value: offset.name,
+                });
+            }
+
+            let entry = files.next_file()?.unwrap();
+            entries.push(EntryInfo {
+                ext: index.ext,
+                name: index.name,
+                mode: index.mode,
+                offset: offset.offset,
+                size: offset.size,
+                variants: entry.variants().to_vec(),
+            });
+        }
+
+        Ok(Self {
+            version: bundle.version,
+            num_files: bundle.num_files,
+            reserved,
+            chunks: chunks.sizes().to_vec(),
+            total_size: chunks.total_size(),
+            entries,
+        })
+    }
+
+    pub fn compressed_size(&self) -> u64 {
+        self.chunks.iter().map(|&size| u64::from(size)).sum()
+    }
+
+    /// Decompressed size divided by compressed size.
+    pub fn ratio(&self) -> f64 {
+        match self.compressed_size() {
+            0 => 0.0,
+            size => self.total_size as f64 / size as f64,
+        }
+    }
+
+    pub fn write_text(&self, mut out: impl Write, dictionary: &HashMap<MurmurHash, &str>) -> io::Result<()> {
+        writeln!(out, "version: {}", self.version)?;
+        writeln!(out, "num_files: {}", self.num_files)?;
+        writeln!(out, "reserved:")?;
+        for (i, line) in self.reserved.chunks(16).enumerate() {
+            write!(out, "  {:04x}:", i * 16)?;
+            for b in line {
+                write!(out, " {b:02x}")?;
+            }
+            writeln!(out)?;
+        }
+
+        writeln!(out, "chunks: {}", self.chunks.len())?;
+        for (i, size) in self.chunks.iter().enumerate() {
+            writeln!(out, "  {i}: {size}")?;
+        }
+        writeln!(out, "compressed size: {}", self.compressed_size())?;
+        writeln!(out, "total size: {}", self.total_size)?;
+        writeln!(out, "ratio: {:.2}", self.ratio())?;
+
+        writeln!(out, "entries:")?;
+        for entry in &self.entries {
+            match extension(entry.ext, dictionary) {
+                Some(ext) => write!(out, "  {ext}")?,
+                None => write!(out, "  {:016x}", entry.ext)?,
+            }
+            match dictionary.get(&MurmurHash(entry.name)) {
+                Some(name) => write!(out, " {name}")?,
+                None => write!(out, " {:016x}", entry.name)?,
+            }
+            writeln!(out, " mode {} offset {} size {}", entry.mode, entry.offset, entry.size)?;
+            for v in &entry.variants {
+                writeln!(
+                    out,
+                    "    kind {} unknown1 {} body {} tail {}",
+                    v.kind,
+                    v.unknown1,
+                    v.body_size,
+                    v.tail_size,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn write_json(&self, mut out: impl Write, dictionary: &HashMap<MurmurHash, &str>) -> io::Result<()> {
+        write!(out, "{{\"version\":{},\"num_files\":{},\"reserved\":\"", self.version, self.num_files)?;
+        for b in &self.reserved {
+            write!(out, "{b:02x}")?;
+        }
+        write!(out, "\",\"chunks\":[")?;
+        for (i, size) in self.chunks.iter().enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            write!(out, "{size}")?;
+        }
+        write!(
+            out,
+            "],\"compressed_size\":{},\"total_size\":{},\"ratio\":{},\"entries\":[",
+            self.compressed_size(),
+            self.total_size,
+            self.ratio(),
+        )?;
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            write!(out, "{{\"ext_hash\":\"{:016x}\",", entry.ext)?;
+            if let Some(ext) = extension(entry.ext, dictionary) {
+                write!(out, "\"ext\":\"{ext}\",")?;
+            }
+            write!(out, "\"name_hash\":\"{:016x}\",", entry.name)?;
+            if let Some(name) = dictionary.get(&MurmurHash(entry.name)) {
+                write!(out, "\"name\":")?;
+                write_json_str(&mut out, name)?;
+                write!(out, ",")?;
+            }
+            write!(
+                out,
+                "\"mode\":{},\"offset\":{},\"size\":{},\"variants\":[",
+                entry.mode,
+                entry.offset,
+                entry.size,
+            )?;
+            for (i, v) in entry.variants.iter().enumerate() {
+                if i > 0 {
+                    write!(out, ",")?;
+                }
+                write!(
+                    out,
+                    "{{\"kind\":{},\"unknown1\":{},\"body_size\":{},\"tail_size\":{}}}",
+                    v.kind,
+                    v.unknown1,
+                    v.body_size,
+                    v.tail_size,
+                )?;
+            }
+            write!(out, "]}}")?;
+        }
+        writeln!(out, "]}}")
+    }
+}
+
+fn extension<'d>(ext: u64, dictionary: &HashMap<MurmurHash, &'d str>) -> Option<&'d str> {
+    extension_name(ext).or_else(|| dictionary.get(&MurmurHash(ext)).copied())
+}
+
+/// Write `s` as a quoted JSON string.
+pub fn write_json_str(mut out: impl Write, s: &str) -> io::Result<()> {
+    write!(out, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{c}")?,
+        }
+    }
+    write!(out, "\"")
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::*;
+    use crate::decompress::Stored;
+    use crate::writer::BundleWriter;
+
+    #[test]
+    fn info() {
+        let lua = crate::hash::murmur_hash64a(b"lua", 0);
+        let mut writer = BundleWriter::new(7);
+        writer.reserved([0xAB; 256]);
+        writer.add_file(lua, 10, 2)
+            .variant(0, 0, b"return", b"");
+        writer.add_file(3, 30, 0)
+            .variant(0, 1, b"english", b"")
+            .variant(1, 0, b"spanish", b"xy");
+        let mut bundle = Vec::new();
+        writer.write(&mut bundle).unwrap();
+
+        let mut rdr = Cursor::new(&bundle[..]);
+        let mut fd = BundleFd::new(None, &mut rdr).unwrap();
+        let info = BundleInfo::read(&mut fd, &Stored).unwrap();
+        assert_eq!(7, info.version);
+        assert_eq!(2, info.num_files);
+        assert_eq!([0xAB; 256], info.reserved);
+        assert_eq!(1, info.chunks.len());
+        assert_eq!(2, info.entries.len());
+        assert_eq!(2, info.entries[0].mode);
+        assert_eq!(vec![
+            Variant { kind: 0, unknown1: 1, body_size: 7, unknown2: 1, tail_size: 0 },
+            Variant { kind: 1, unknown1: 0, body_size: 7, unknown2: 1, tail_size: 2 },
+        ], info.entries[1].variants);
+
+        let dictionary = HashMap::from([(MurmurHash(10), "scripts/\"main\"")]);
+        let mut json = Vec::new();
+        info.write_json(&mut json, &dictionary).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.starts_with("{\"version\":7,\"num_files\":2,\"reserved\":\"abab"));
+        assert!(json.contains(&format!(
+            "{{\"ext_hash\":\"{lua:016x}\",\"ext\":\"lua\",\"name_hash\":\"000000000000000a\",\
+            \"name\":\"scripts/\\\"main\\\"\",\"mode\":2,\"offset\":0,"
+        )));
+        assert!(json.contains("{\"kind\":1,\"unknown1\":0,\"body_size\":7,\"tail_size\":2}"));
+    }
+}
@@ -0,0 +1,1943 @@
+//! Pure Rust decoder for Oodle compressed data.
+//!
+//! Only the Kraken and Mermaid (and Selkie, which shares the Mermaid
+//! bitstream) decoders are implemented since those are the ones Darktide
+//! bundles use. Checksums are not verified.
+//!
+//! Ported from ooz by Powzix.
+// https://github.com/powzix/ooz/blob/master/kraken.cpp
+use std::borrow::Cow;
+use std::io;
+
+use crate::decompress::Decompressor;
+
+// bytes decoded per block header
+const QUANTUM: usize = 0x40000;
+// bytes decoded per LZ table
+const SUB_CHUNK: usize = 0x20000;
+// upper bound of intermediate buffers in the original decoder
+const SCRATCH_SIZE: usize = 0x6C000;
+
+const CODE_PREFIX: [u32; 12] = [0x0, 0x0, 0x2, 0x6, 0xE, 0x1E, 0x3E, 0x7E, 0xFE, 0x1FE, 0x2FE, 0x3FE];
+
+/// Decompressor for Kraken and Mermaid without the Oodle library.
+pub struct Kraken;
+
+impl Decompressor for Kraken {
+    fn memory_size_needed(&self) -> io::Result<u64> {
+        Ok(0)
+    }
+
+    fn decompress(&self, data: &[u8], out: &mut [u8], _scratch: &mut [u8]) -> io::Result<u64> {
+        decompress(data, out)?;
+        Ok(out.len() as u64)
+    }
+}
+
+/// Decompress `src` filling `out` completely. Returns the number of bytes
+/// of `src` used.
+pub fn decompress(src: &[u8], out: &mut [u8]) -> io::Result<usize> {
+    let mut pos = 0;
+    let mut dst = 0;
+    let mut header = BlockHeader::default();
+    while dst < out.len() {
+        if dst & (QUANTUM - 1) == 0 {
+            header = BlockHeader::parse(&src[pos..])?;
+            pos += 2;
+        }
+
+        let len = QUANTUM.min(out.len() - dst);
+        if header.uncompressed {
+            ensure(src.len() - pos >= len, "truncated block")?;
+            out[dst..dst + len].copy_from_slice(&src[pos..pos + len]);
+            pos += len;
+            dst += len;
+            continue;
+        }
+
+        let (quantum, n) = Quantum::parse(&src[pos..], header.use_checksums)?;
+        pos += n;
+        match quantum {
+            Quantum::Memset(byte) => out[dst..dst + len].fill(byte),
+            Quantum::Compressed(size) => {
+                ensure(src.len() - pos >= size, "truncated block")?;
+                ensure(size <= len, "block is larger than its output")?;
+
+                let data = &src[pos..pos + size];
+                if size == len {
+                    out[dst..dst + len].copy_from_slice(data);
+                } else {
+                    let used = decode_quantum(header.decoder, data, out, dst, dst + len)?;
+                    ensure(used == size, "block size mismatch")?;
+                }
+                pos += size;
+            }
+        }
+        dst += len;
+    }
+
+    Ok(pos)
+}
+
+fn corrupt(what: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what)
+}
+
+fn ensure(cond: bool, what: &'static str) -> io::Result<()> {
+    if cond {
+        Ok(())
+    } else {
+        Err(corrupt(what))
+    }
+}
+
+// shifts that are 0 instead of overflowing
+fn shl(x: u32, n: u32) -> u32 {
+    x.checked_shl(n).unwrap_or(0)
+}
+
+fn shr(x: u32, n: u32) -> u32 {
+    x.checked_shr(n).unwrap_or(0)
+}
+
+// reads past either end of `src` are zero
+fn byte_at(src: &[u8], i: isize) -> u32 {
+    if i >= 0 && (i as usize) < src.len() {
+        src[i as usize] as u32
+    } else {
+        0
+    }
+}
+
+fn le16_at(src: &[u8], i: isize) -> u32 {
+    byte_at(src, i) | byte_at(src, i + 1) << 8
+}
+
+fn le32_at(src: &[u8], i: isize) -> u32 {
+    if i >= 0 && i as usize + 4 <= src.len() {
+        let i = i as usize;
+        u32::from_le_bytes(src[i..i + 4].try_into().unwrap())
+    } else {
+        le16_at(src, i) | le16_at(src, i + 2) << 16
+    }
+}
+
+fn be24(src: &[u8]) -> u32 {
+    (src[0] as u32) << 16 | (src[1] as u32) << 8 | src[2] as u32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecoderType {
+    Kraken,
+    Mermaid,
+}
+
+#[derive(Default)]
+struct BlockHeader {
+    decoder: Option<DecoderType>,
+    uncompressed: bool,
+    use_checksums: bool,
+}
+
+impl BlockHeader {
+    fn parse(src: &[u8]) -> io::Result<Self> {
+        ensure(src.len() >= 2, "truncated block header")?;
+        ensure(src[0] & 0xF == 0xC && (src[0] >> 4) & 3 == 0, "invalid block header")?;
+        let decoder = match src[1] & 0x7F {
+            6 => DecoderType::Kraken,
+            10 => DecoderType::Mermaid,
+            5 => return Err(corrupt("LZNA is not supported")),
+            11 => return Err(corrupt("Bitknit is not supported")),
+            12 => return Err(corrupt("Leviathan is not supported")),
+            _ => return Err(corrupt("invalid block header")),
+        };
+
+        Ok(Self {
+            decoder: Some(decoder),
+            uncompressed: (src[0] >> 6) & 1 == 1,
+            use_checksums: src[1] >> 7 == 1,
+        })
+    }
+}
+
+enum Quantum {
+    Compressed(usize),
+    Memset(u8),
+}
+
+impl Quantum {
+    fn parse(src: &[u8], use_checksums: bool) -> io::Result<(Self, usize)> {
+        ensure(src.len() >= 3, "truncated quantum header")?;
+        let v = be24(src);
+        let size = v & 0x3FFFF;
+        if size != 0x3FFFF {
+            // checksum of the compressed data
+            let len = if use_checksums { 6 } else { 3 };
+            ensure(src.len() >= len, "truncated quantum header")?;
+            Ok((Quantum::Compressed(size as usize + 1), len))
+        } else if v >> 18 == 1 {
+            ensure(src.len() >= 4, "truncated quantum header")?;
+            Ok((Quantum::Memset(src[3]), 4))
+        } else {
+            Err(corrupt("invalid quantum header"))
+        }
+    }
+}
+
+fn decode_quantum(
+    decoder: Option<DecoderType>,
+    src: &[u8],
+    out: &mut [u8],
+    mut dst: usize,
+    end: usize,
+) -> io::Result<usize> {
+    let mut pos = 0;
+    while dst != end {
+        let count = (end - dst).min(SUB_CHUNK);
+        ensure(src.len() - pos >= 4, "truncated chunk")?;
+        let header = be24(&src[pos..]);
+        let used;
+        if header & 0x800000 == 0 {
+            // entropy coded without any matches
+            let (data, n) = decode_bytes(&src[pos..], count)?;
+            ensure(data.len() == count, "chunk size mismatch")?;
+            out[dst..dst + count].copy_from_slice(&data);
+            used = n;
+        } else {
+            pos += 3;
+            used = (header & 0x7FFFF) as usize;
+            let mode = (header >> 19) & 0xF;
+            ensure(src.len() - pos >= used, "truncated chunk")?;
+            let data = &src[pos..pos + used];
+            if used < count {
+                match decoder {
+                    Some(DecoderType::Kraken) => {
+                        let lz = KrakenLzTable::read(mode, data, out, dst, count)?;
+                        lz.process_runs(mode, out, dst, dst + count)?;
+                    }
+                    Some(DecoderType::Mermaid) => {
+                        let lz = MermaidLzTable::read(mode, data, out, dst, count)?;
+                        lz.process_runs(mode, data, out, dst, count)?;
+                    }
+                    None => return Err(corrupt("missing block header")),
+                }
+            } else if used > count || mode != 0 {
+                return Err(corrupt("invalid chunk header"));
+            } else {
+                out[dst..dst + count].copy_from_slice(data);
+            }
+        }
+        pos += used;
+        dst += count;
+    }
+
+    Ok(pos)
+}
+
+// header of an entropy coded block
+struct BytesHeader {
+    kind: u8,
+    src_size: usize,
+    dst_size: usize,
+    len: usize,
+}
+
+impl BytesHeader {
+    fn parse(src: &[u8], capacity: usize) -> io::Result<Self> {
+        ensure(src.len() >= 2, "truncated entropy block")?;
+        let kind = (src[0] >> 4) & 0x7;
+        if kind == 0 {
+            // stored
+            let (size, len) = if src[0] >= 0x80 {
+                (((src[0] as usize) << 8 | src[1] as usize) & 0xFFF, 2)
+            } else {
+                ensure(src.len() >= 3, "truncated entropy block")?;
+                let size = be24(src) as usize;
+                ensure(size & !0x3FFFF == 0, "invalid entropy block")?;
+                (size, 3)
+            };
+            ensure(size <= capacity, "entropy block is too large")?;
+            ensure(src.len() - len >= size, "truncated entropy block")?;
+            return Ok(Self { kind, src_size: size, dst_size: size, len });
+        }
+        ensure(kind <= 5, "invalid entropy block")?;
+
+        let (src_size, dst_size, len) = if src[0] >= 0x80 {
+            ensure(src.len() >= 3, "truncated entropy block")?;
+            let bits = be24(src) as usize;
+            let src_size = bits & 0x3FF;
+            (src_size, src_size + ((bits >> 10) & 0x3FF) + 1, 3)
+        } else {
+            ensure(src.len() >= 5, "truncated entropy block")?;
+            let bits = u32::from_be_bytes(src[1..5].try_into().unwrap());
+            let src_size = (bits & 0x3FFFF) as usize;
+            let dst_size = (((bits >> 18) | (src[0] as u32) << 14) & 0x3FFFF) as usize + 1;
+            ensure(src_size < dst_size, "invalid entropy block")?;
+            (src_size, dst_size, 5)
+        };
+        ensure(src.len() - len >= src_size, "truncated entropy block")?;
+        ensure(dst_size <= capacity, "entropy block is too large")?;
+
+        Ok(Self { kind, src_size, dst_size, len })
+    }
+}
+
+// Decode an entropy coded block of at most `capacity` bytes from the start of
+// `src`. Returns the block and the number of bytes of `src` used.
+fn decode_bytes(src: &[u8], capacity: usize) -> io::Result<(Vec<u8>, usize)> {
+    let header = BytesHeader::parse(src, capacity)?;
+    let data = &src[header.len..header.len + header.src_size];
+    if header.kind == 0 {
+        return Ok((data.to_vec(), header.len + header.src_size));
+    }
+
+    let mut out = vec![0; header.dst_size];
+    let used = match header.kind {
+        1 => decode_tans(data, &mut out)?,
+        2 | 4 => decode_huffman(data, &mut out, header.kind >> 1)?,
+        3 => decode_rle(data, &mut out)?,
+        5 => decode_recursive(data, &mut out)?,
+        _ => unreachable!(),
+    };
+    ensure(used == header.src_size, "entropy block size mismatch")?;
+
+    Ok((out, header.len + header.src_size))
+}
+
+struct BitReader<'a> {
+    src: &'a [u8],
+    // next byte to refill from
+    p: isize,
+    // end of the stream or start when reading backwards
+    p_end: isize,
+    bits: u32,
+    bitpos: i32,
+    backwards: bool,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(src: &'a [u8]) -> Self {
+        let mut br = Self {
+            src,
+            p: 0,
+            p_end: src.len() as isize,
+            bits: 0,
+            bitpos: 24,
+            backwards: false,
+        };
+        br.refill();
+        br
+    }
+
+    fn new_backwards(src: &'a [u8]) -> Self {
+        let mut br = Self {
+            src,
+            p: src.len() as isize,
+            p_end: 0,
+            bits: 0,
+            bitpos: 24,
+            backwards: true,
+        };
+        br.refill();
+        br
+    }
+
+    fn refill(&mut self) {
+        while self.bitpos > 0 {
+            let byte = if self.backwards {
+                self.p -= 1;
+                if self.p >= self.p_end { byte_at(self.src, self.p) } else { 0 }
+            } else {
+                self.p += 1;
+                if self.p - 1 < self.p_end { byte_at(self.src, self.p - 1) } else { 0 }
+            };
+            self.bits |= shl(byte, self.bitpos as u32);
+            self.bitpos -= 8;
+        }
+    }
+
+    // position of the next unread byte
+    fn position(&self) -> isize {
+        self.p - ((24 - self.bitpos) / 8) as isize
+    }
+
+    // restart reading at byte `p` skipping `bitpos` bits
+    fn reset(&mut self, p: usize, bitpos: u32) {
+        self.bitpos = 24;
+        self.p = p as isize;
+        self.bits = 0;
+        self.refill();
+        self.bits = shl(self.bits, bitpos);
+        self.bitpos += bitpos as i32;
+    }
+
+    fn read_bit(&mut self) -> u32 {
+        self.refill();
+        self.read_bits(1)
+    }
+
+    fn read_bits(&mut self, n: u32) -> u32 {
+        let r = shr(self.bits, 32 - n);
+        self.bits = shl(self.bits, n);
+        self.bitpos += n as i32;
+        r
+    }
+
+    fn read_more_than_24_bits(&mut self, n: u32) -> u32 {
+        let rv = if n <= 24 {
+            self.read_bits(n)
+        } else {
+            let hi = self.read_bits(24) << (n - 24);
+            self.refill();
+            hi + self.read_bits(n - 24)
+        };
+        self.refill();
+        rv
+    }
+
+    fn read_distance(&mut self, v: u32) -> u32 {
+        let n = if v < 0xF0 { (v >> 4) + 4 } else { v - 0xF0 + 4 };
+        let w = (self.bits | 1).rotate_left(n);
+        self.bitpos += n as i32;
+        let m = (2 << n) - 1;
+        self.bits = w & !m;
+        let rv = if v < 0xF0 {
+            ((w & m) << 4) + (v & 0xF) - 248
+        } else {
+            let rv = ((w & m) << 12).wrapping_add(8322816);
+            self.refill();
+            let rv = rv.wrapping_add(self.bits >> 20);
+            self.bitpos += 12;
+            self.bits <<= 12;
+            rv
+        };
+        self.refill();
+        rv
+    }
+
+    fn read_length(&mut self) -> Option<u32> {
+        let n = self.bits.leading_zeros();
+        if n > 12 {
+            return None;
+        }
+        self.bitpos += n as i32;
+        self.bits <<= n;
+        self.refill();
+        let n = n + 7;
+        self.bitpos += n as i32;
+        let rv = (self.bits >> (32 - n)).wrapping_sub(64);
+        self.bits <<= n;
+        self.refill();
+        Some(rv)
+    }
+
+    // number of code lengths used for ranges of unused symbols
+    fn read_fluff(&mut self, num_symbols: usize) -> usize {
+        if num_symbols == 256 {
+            return 0;
+        }
+
+        let x = (257 - num_symbols).min(num_symbols) as u32 * 2;
+        let y = 32 - (x - 1).leading_zeros();
+        let v = self.bits >> (32 - y);
+        let z = (1 << y) - x;
+        if (v >> 1) >= z {
+            self.bits <<= y;
+            self.bitpos += y as i32;
+            (v - z) as usize
+        } else {
+            self.bits <<= y - 1;
+            self.bitpos += y as i32 - 1;
+            (v >> 1) as usize
+        }
+    }
+
+    // switch to reading golomb rice codes at the current byte
+    fn rice(&self) -> RiceReader {
+        RiceReader {
+            p: (self.p - ((24 - self.bitpos + 7) >> 3) as isize) as usize,
+            bitpos: ((self.bitpos - 24) & 7) as u32,
+        }
+    }
+}
+
+struct RiceReader {
+    p: usize,
+    // bits already read of the byte at `p`
+    bitpos: u32,
+}
+
+impl RiceReader {
+    // Each length is the number of zero bits before a set bit.
+    fn lengths(&mut self, src: &[u8], dst: &mut [u8]) -> io::Result<()> {
+        ensure(self.p < src.len(), "truncated code lengths")?;
+        let mut count = 0u32;
+        let mut i = 0;
+        while i < dst.len() {
+            ensure(self.p < src.len(), "truncated code lengths")?;
+            if (src[self.p] >> (7 - self.bitpos)) & 1 == 1 {
+                dst[i] = count as u8;
+                count = 0;
+                i += 1;
+            } else {
+                count += 1;
+            }
+            self.bitpos += 1;
+            if self.bitpos == 8 {
+                self.bitpos = 0;
+                self.p += 1;
+            }
+        }
+        Ok(())
+    }
+
+    // Append `bitcount` low bits to every value of `dst`.
+    fn bits(&mut self, src: &[u8], dst: &mut [u8], bitcount: u32) -> io::Result<()> {
+        if bitcount == 0 {
+            return Ok(());
+        }
+
+        let bits_required = self.bitpos as usize + bitcount as usize * dst.len();
+        ensure((bits_required + 7) >> 3 <= src.len() - self.p, "truncated code lengths")?;
+        let mut bit = self.p * 8 + self.bitpos as usize;
+        for v in dst.iter_mut() {
+            let mut x = *v as u32;
+            for _ in 0..bitcount {
+                x = x << 1 | (src[bit >> 3] as u32 >> (7 - (bit & 7))) & 1;
+                bit += 1;
+            }
+            *v = x as u8;
+        }
+        self.p += bits_required >> 3;
+        self.bitpos = (bits_required & 7) as u32;
+        Ok(())
+    }
+}
+
+// (first symbol, number of symbols)
+fn convert_to_ranges(
+    br: &mut BitReader,
+    num_symbols: usize,
+    fluff: usize,
+    symlen: &[u8],
+) -> io::Result<Vec<(usize, usize)>> {
+    let num_ranges = fluff >> 1;
+    let mut symlen = symlen.iter().map(|&v| v as u32);
+    let mut next = || symlen.next().unwrap_or(0);
+    let mut sym_idx = 0;
+
+    // starts with unused symbols
+    if fluff & 1 == 1 {
+        br.refill();
+        let v = next();
+        ensure(v < 8, "invalid symbol range")?;
+        sym_idx = (br.read_bits(v + 1) + (1 << (v + 1)) - 1) as usize;
+    }
+
+    let mut ranges = Vec::with_capacity(num_ranges + 1);
+    let mut syms_used = 0;
+    for _ in 0..num_ranges {
+        br.refill();
+        let v = next();
+        ensure(v < 9, "invalid symbol range")?;
+        let num = (br.read_bits(v) + (1 << v)) as usize;
+        let v = next();
+        ensure(v < 8, "invalid symbol range")?;
+        let space = (br.read_bits(v + 1) + (1 << (v + 1)) - 1) as usize;
+        ranges.push((sym_idx, num));
+        syms_used += num;
+        sym_idx += num + space;
+    }
+
+    ensure(sym_idx < 256
+        && syms_used < num_symbols
+        && sym_idx + num_symbols - syms_used <= 256, "invalid symbol range")?;
+    ranges.push((sym_idx, num_symbols - syms_used));
+    Ok(ranges)
+}
+
+fn read_code_lengths_old(br: &mut BitReader, syms: &mut [u8], prefix: &mut [u32; 12]) -> io::Result<usize> {
+    if br.read_bits(1) == 1 {
+        let mut sym = 0;
+        let mut num_symbols = 0;
+        let mut avg_bits_x4 = 32;
+        let forced_bits = br.read_bits(2);
+        let thres_for_valid_gamma_bits = 1 << (31 - (20 >> forced_bits));
+        let mut skip_zeros = br.read_bit() == 1;
+        loop {
+            if !skip_zeros {
+                // run of zeros
+                ensure(br.bits & 0xFF000000 != 0, "invalid code lengths")?;
+                let lz = br.bits.leading_zeros();
+                sym += br.read_bits(2 * (lz + 1)) as usize - 1;
+                if sym >= 256 {
+                    break;
+                }
+            }
+            skip_zeros = false;
+
+            br.refill();
+            ensure(br.bits & 0xFF000000 != 0, "invalid code lengths")?;
+            let lz = br.bits.leading_zeros();
+            let n = br.read_bits(2 * (lz + 1)) as usize - 1;
+            ensure(sym + n <= 256, "invalid code lengths")?;
+            br.refill();
+            num_symbols += n;
+            for _ in 0..n {
+                ensure(br.bits >= thres_for_valid_gamma_bits, "invalid code lengths")?;
+                let lz = br.bits.leading_zeros();
+                let v = br.read_bits(lz + forced_bits + 1) as i32 + ((lz as i32 - 1) << forced_bits);
+                let codelen = (-(v & 1) ^ (v >> 1)) + ((avg_bits_x4 + 2) >> 2);
+                ensure((1..=11).contains(&codelen), "invalid code lengths")?;
+                avg_bits_x4 = codelen + ((3 * avg_bits_x4 + 2) >> 2);
+                br.refill();
+                syms[prefix[codelen as usize] as usize] = sym as u8;
+                prefix[codelen as usize] += 1;
+                sym += 1;
+            }
+            if sym == 256 {
+                break;
+            }
+        }
+        ensure(sym == 256 && num_symbols >= 2, "invalid code lengths")?;
+        Ok(num_symbols)
+    } else {
+        // sparse symbols
+        let num_symbols = br.read_bits(8) as usize;
+        ensure(num_symbols != 0, "invalid code lengths")?;
+        if num_symbols == 1 {
+            syms[0] = br.read_bits(8) as u8;
+        } else {
+            let codelen_bits = br.read_bits(3);
+            ensure(codelen_bits <= 4, "invalid code lengths")?;
+            for _ in 0..num_symbols {
+                br.refill();
+                let sym = br.read_bits(8) as u8;
+                let codelen = br.read_bits(codelen_bits) as usize + 1;
+                ensure(codelen <= 11, "invalid code lengths")?;
+                syms[prefix[codelen] as usize] = sym;
+                prefix[codelen] += 1;
+            }
+        }
+        Ok(num_symbols)
+    }
+}
+
+fn read_code_lengths_new(br: &mut BitReader, syms: &mut [u8], prefix: &mut [u32; 12]) -> io::Result<usize> {
+    let forced_bits = br.read_bits(2);
+    let num_symbols = br.read_bits(8) as usize + 1;
+    let fluff = br.read_fluff(num_symbols);
+
+    let mut code_len = [0u8; 512 + 16];
+    let mut rice = br.rice();
+    rice.lengths(br.src, &mut code_len[..num_symbols + fluff])?;
+    rice.bits(br.src, &mut code_len[..num_symbols], forced_bits)?;
+    br.reset(rice.p, rice.bitpos);
+
+    let mut running_sum = 0x1Eu32;
+    for len in &mut code_len[..num_symbols] {
+        let v = *len as i32;
+        let v = -(v & 1) ^ (v >> 1);
+        *len = (v + (running_sum >> 2) as i32 + 1) as u8;
+        ensure((1..=11).contains(len), "invalid code lengths")?;
+        running_sum = running_sum.wrapping_add(v as u32);
+    }
+
+    let ranges = convert_to_ranges(br, num_symbols, fluff, &code_len[num_symbols..])?;
+    let mut lens = code_len.iter();
+    for (sym, num) in ranges {
+        for sym in sym..sym + num {
+            let len = *lens.next().unwrap() as usize;
+            syms[prefix[len] as usize] = sym as u8;
+            prefix[len] += 1;
+        }
+    }
+
+    Ok(num_symbols)
+}
+
+// lookup tables indexed by the next 11 bits of a stream, least significant
+// bit first
+struct HuffLut {
+    bits2len: [u8; 2048],
+    bits2sym: [u8; 2048],
+}
+
+impl HuffLut {
+    fn new(prefix: &[u32; 12], syms: &[u8]) -> io::Result<Self> {
+        let mut bits2len = [0; 2048];
+        let mut bits2sym = [0; 2048];
+        let mut slot = 0;
+        for len in 1..11 {
+            let start = CODE_PREFIX[len] as usize;
+            let count = prefix[len] as usize - start;
+            let step = 1 << (11 - len);
+            ensure(slot + (count << (11 - len)) <= 2048, "invalid huffman table")?;
+            for &sym in &syms[start..start + count] {
+                bits2len[slot..slot + step].fill(len as u8);
+                bits2sym[slot..slot + step].fill(sym);
+                slot += step;
+            }
+        }
+        let start = CODE_PREFIX[11] as usize;
+        let count = prefix[11] as usize - start;
+        ensure(slot + count <= 2048, "invalid huffman table")?;
+        bits2len[slot..slot + count].fill(11);
+        bits2sym[slot..slot + count].copy_from_slice(&syms[start..start + count]);
+        ensure(slot + count == 2048, "invalid huffman table")?;
+
+        let mut lut = Self {
+            bits2len: [0; 2048],
+            bits2sym: [0; 2048],
+        };
+        for i in 0..2048 {
+            let rev = ((i as u16).reverse_bits() >> 5) as usize;
+            lut.bits2len[i] = bits2len[rev];
+            lut.bits2sym[i] = bits2sym[rev];
+        }
+        Ok(lut)
+    }
+}
+
+fn decode_huffman(src: &[u8], out: &mut [u8], kind: u8) -> io::Result<usize> {
+    let mut br = BitReader::new(src);
+    let mut prefix = CODE_PREFIX;
+    let mut syms = [0; 1280];
+    let num_syms = if br.read_bits(1) == 0 {
+        read_code_lengths_old(&mut br, &mut syms, &mut prefix)?
+    } else if br.read_bits(1) == 0 {
+        read_code_lengths_new(&mut br, &mut syms, &mut prefix)?
+    } else {
+        return Err(corrupt("invalid huffman table"));
+    };
+
+    let pos = br.position();
+    ensure(num_syms >= 1 && pos >= 0 && pos as usize <= src.len(), "invalid huffman table")?;
+    let pos = pos as usize;
+    if num_syms == 1 {
+        out.fill(syms[0]);
+        return Ok(pos);
+    }
+
+    let lut = HuffLut::new(&prefix, &syms)?;
+    let len = src.len();
+    if kind == 1 {
+        ensure(pos + 3 <= len, "truncated huffman block")?;
+        let mid = pos + 2 + le16_at(src, pos as isize) as usize;
+        huffman_streams(src, pos + 2, mid, len, out, &lut)?;
+    } else {
+        ensure(pos + 6 <= len, "truncated huffman block")?;
+        let half = (out.len() + 1) >> 1;
+        let split_mid = le32_at(src, pos as isize) as usize & 0xFFFFFF;
+        let pos = pos + 3;
+        ensure(split_mid <= len - pos, "invalid huffman block")?;
+        let mid = pos + split_mid;
+        let split_left = le16_at(src, pos as isize) as usize;
+        let pos = pos + 2;
+        ensure(mid >= pos + split_left + 2 && len - mid >= 3, "invalid huffman block")?;
+        let split_right = le16_at(src, mid as isize) as usize;
+        ensure(len - (mid + 2) >= split_right + 2, "invalid huffman block")?;
+
+        let (left, right) = out.split_at_mut(half);
+        huffman_streams(src, pos, pos + split_left, mid, left, &lut)?;
+        huffman_streams(src, mid + 2, mid + 2 + split_right, len, right, &lut)?;
+    }
+
+    Ok(len)
+}
+
+// Decode three interleaved streams of `src`. Two are read forwards from
+// `start` and `mid` while the last is read backwards from `end` until it
+// meets the middle stream.
+fn huffman_streams(
+    src: &[u8],
+    start: usize,
+    mid: usize,
+    end: usize,
+    out: &mut [u8],
+    lut: &HuffLut,
+) -> io::Result<()> {
+    let mut s = start as isize;
+    let mut s_bits = 0u32;
+    let mut s_pos = 0i32;
+    let mut m = mid as isize;
+    let mut m_bits = 0u32;
+    let mut m_pos = 0i32;
+    let mut e = end as isize;
+    let mut e_bits = 0u32;
+    let mut e_pos = 0i32;
+    let mut d = 0;
+    let mut d_end = out.len();
+
+    macro_rules! decode {
+        ($bits:ident, $pos:ident) => {{
+            let k = ($bits & 0x7FF) as usize;
+            let n = lut.bits2len[k];
+            $bits >>= n;
+            $pos -= n as i32;
+            lut.bits2sym[k]
+        }};
+    }
+
+    ensure(s <= m, "invalid huffman block")?;
+    if e - m >= 4 && d_end - d >= 6 {
+        d_end -= 5;
+        e -= 4;
+        while d < d_end && s <= m && m <= e {
+            s_bits |= le32_at(src, s) << s_pos;
+            s += ((31 - s_pos) >> 3) as isize;
+            e_bits |= le32_at(src, e).swap_bytes() << e_pos;
+            e -= ((31 - e_pos) >> 3) as isize;
+            m_bits |= le32_at(src, m) << m_pos;
+            m += ((31 - m_pos) >> 3) as isize;
+            s_pos |= 0x18;
+            e_pos |= 0x18;
+            m_pos |= 0x18;
+
+            for i in 0..2 {
+                out[d + 3 * i] = decode!(s_bits, s_pos);
+                out[d + 3 * i + 1] = decode!(e_bits, e_pos);
+                out[d + 3 * i + 2] = decode!(m_bits, m_pos);
+            }
+            d += 6;
+        }
+        d_end += 5;
+
+        s -= (s_pos >> 3) as isize;
+        s_pos &= 7;
+        e += 4 + (e_pos >> 3) as isize;
+        e_pos &= 7;
+        m -= (m_pos >> 3) as isize;
+        m_pos &= 7;
+    }
+
+    while d < d_end {
+        if m - s <= 1 {
+            if m - s == 1 {
+                s_bits |= byte_at(src, s) << s_pos;
+            }
+        } else {
+            s_bits |= le16_at(src, s) << s_pos;
+        }
+        out[d] = decode!(s_bits, s_pos);
+        d += 1;
+        s += ((7 - s_pos) >> 3) as isize;
+        s_pos &= 7;
+
+        if d < d_end {
+            if e - m <= 1 {
+                if e - m == 1 {
+                    e_bits |= byte_at(src, m) << e_pos;
+                    m_bits |= byte_at(src, m) << m_pos;
+                }
+            } else {
+                e_bits |= ((le16_at(src, e - 2) as u16).swap_bytes() as u32) << e_pos;
+                m_bits |= le16_at(src, m) << m_pos;
+            }
+            out[d] = decode!(e_bits, e_pos);
+            d += 1;
+            e -= ((7 - e_pos) >> 3) as isize;
+            e_pos &= 7;
+
+            if d < d_end {
+                out[d] = decode!(m_bits, m_pos);
+                d += 1;
+                m += ((7 - m_pos) >> 3) as isize;
+                m_pos &= 7;
+            }
+        }
+        ensure(s <= m && m <= e, "invalid huffman block")?;
+    }
+    ensure(s == mid as isize && e == m, "huffman block size mismatch")?;
+    Ok(())
+}
+
+struct TansTable {
+    // symbols with a weight of 1
+    a: Vec<u8>,
+    // symbol << 16 | weight
+    b: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct TansLutEnt {
+    x: u32,
+    bits_x: u8,
+    symbol: u8,
+    w: u32,
+}
+
+impl TansTable {
+    fn read(br: &mut BitReader, l_bits: u32) -> io::Result<Self> {
+        let l = 1 << l_bits;
+        let mut a = Vec::new();
+        let mut b = Vec::new();
+        br.refill();
+        if br.read_bits(1) == 1 {
+            let q = br.read_bits(3);
+            let num_symbols = br.read_bits(8) as usize + 1;
+            ensure(num_symbols >= 2, "invalid tans table")?;
+            let fluff = br.read_fluff(num_symbols);
+            let total = num_symbols + fluff;
+
+            let mut rice = [0u8; 512 + 16];
+            let mut rr = br.rice();
+            rr.lengths(br.src, &mut rice[..total])?;
+            br.reset(rr.p, rr.bitpos);
+
+            let ranges = convert_to_ranges(br, num_symbols, fluff, &rice[num_symbols..])?;
+            br.refill();
+
+            let mut lens = rice.iter();
+            let mut average = 6;
+            let mut sum = 0;
+            for (symbol, num) in ranges {
+                for symbol in symbol..symbol + num {
+                    br.refill();
+                    let nextra = q + *lens.next().unwrap() as u32;
+                    ensure(nextra <= 15, "invalid tans table")?;
+                    let mut v = (br.read_bits(nextra) + (1 << nextra) - (1 << q)) as i32;
+                    let average_div4 = average >> 2;
+                    let mut limit = 2 * average_div4;
+                    if v <= limit {
+                        v = average_div4 + (-(v & 1) ^ ((v as u32) >> 1) as i32);
+                    }
+                    if limit > v {
+                        limit = v;
+                    }
+                    v += 1;
+                    average += limit - average_div4;
+                    if v == 1 {
+                        a.push(symbol as u8);
+                    } else if v >= 2 {
+                        b.push((symbol as u32) << 16 | v as u32);
+                    }
+                    sum += v;
+                }
+            }
+            ensure(sum == l as i32, "invalid tans table")?;
+        } else {
+            let mut seen = [false; 256];
+            let count = br.read_bits(3) + 1;
+            let bits_per_sym = 32 - l_bits.leading_zeros();
+            let max_delta_bits = br.read_bits(bits_per_sym);
+            ensure(max_delta_bits != 0 && max_delta_bits <= l_bits, "invalid tans table")?;
+
+            let mut weight = 0;
+            let mut total = 0;
+            for _ in 0..count {
+                br.refill();
+                let sym = br.read_bits(8) as usize;
+                ensure(!seen[sym], "invalid tans table")?;
+                weight += br.read_bits(max_delta_bits);
+                ensure(weight != 0, "invalid tans table")?;
+                seen[sym] = true;
+                if weight == 1 {
+                    a.push(sym as u8);
+                } else {
+                    b.push((sym as u32) << 16 | weight);
+                }
+                total += weight;
+            }
+
+            br.refill();
+            let sym = br.read_bits(8) as usize;
+            ensure(!seen[sym], "invalid tans table")?;
+            ensure(total < l && l - total >= weight && l - total > 1, "invalid tans table")?;
+            b.push((sym as u32) << 16 | (l - total));
+        }
+
+        Ok(Self { a, b })
+    }
+
+    fn lut(&self, l_bits: u32) -> io::Result<Vec<TansLutEnt>> {
+        let l = 1usize << l_bits;
+        ensure(self.a.len() <= l, "invalid tans table")?;
+        let slots_left = l - self.a.len();
+        let sa = slots_left >> 2;
+        let mut pointers = [0; 4];
+        let mut sb = sa + ((slots_left & 3) > 0) as usize;
+        pointers[1] = sb;
+        sb += sa + ((slots_left & 3) > 1) as usize;
+        pointers[2] = sb;
+        sb += sa + ((slots_left & 3) > 2) as usize;
+        pointers[3] = sb;
+
+        let mut lut = vec![TansLutEnt::default(); l];
+        for (i, &symbol) in self.a.iter().enumerate() {
+            lut[slots_left + i] = TansLutEnt {
+                x: (1 << l_bits) - 1,
+                bits_x: l_bits as u8,
+                symbol,
+                w: 0,
+            };
+        }
+
+        let put = |lut: &mut Vec<TansLutEnt>, ptr: &mut usize, le: TansLutEnt| {
+            let slot = lut.get_mut(*ptr).ok_or_else(|| corrupt("invalid tans table"))?;
+            *slot = le;
+            *ptr += 1;
+            Ok::<(), io::Error>(())
+        };
+
+        let mut weights_sum = 0i32;
+        for &b in &self.b {
+            let weight = (b & 0xFFFF) as i32;
+            let symbol = (b >> 16) as u8;
+            if weight > 4 {
+                let sym_bits = 31 - (weight as u32).leading_zeros();
+                ensure(sym_bits <= l_bits, "invalid tans table")?;
+                let mut z = l_bits - sym_bits;
+                let mut le = TansLutEnt {
+                    x: (1 << z) - 1,
+                    bits_x: z as u8,
+                    symbol,
+                    w: (l as u32 - 1) & ((weight as u32) << z),
+                };
+                let mut what_to_add = 1 << z;
+                let mut x = (1 << (sym_bits + 1)) - weight;
+                for (j, ptr) in pointers.iter_mut().enumerate() {
+                    let y = (weight + ((weights_sum - j as i32 - 1) & 3)) >> 2;
+                    if x >= y {
+                        for _ in 0..y {
+                            put(&mut lut, ptr, le)?;
+                            le.w += what_to_add;
+                        }
+                        x -= y;
+                    } else {
+                        for _ in 0..x {
+                            put(&mut lut, ptr, le)?;
+                            le.w += what_to_add;
+                        }
+                        ensure(z > 0, "invalid tans table")?;
+                        z -= 1;
+                        what_to_add >>= 1;
+                        le.bits_x = z as u8;
+                        le.w = 0;
+                        le.x >>= 1;
+                        for _ in 0..y - x {
+                            put(&mut lut, ptr, le)?;
+                            le.w += what_to_add;
+                        }
+                        x = weight;
+                    }
+                }
+            } else {
+                ensure(weight > 0, "invalid tans table")?;
+                let mut bits = ((1u32 << weight) - 1) << (weights_sum & 3);
+                bits |= bits >> 4;
+                for ww in weight as u32..2 * weight as u32 {
+                    let idx = bits.trailing_zeros() as usize;
+                    bits &= bits - 1;
+                    let weight_bits = 31 - ww.leading_zeros();
+                    ensure(weight_bits <= l_bits, "invalid tans table")?;
+                    let bits_x = l_bits - weight_bits;
+                    put(&mut lut, &mut pointers[idx], TansLutEnt {
+                        x: (1 << bits_x) - 1,
+                        bits_x: bits_x as u8,
+                        symbol,
+                        w: (l as u32 - 1) & (ww << bits_x),
+                    })?;
+                }
+            }
+            weights_sum += weight;
+        }
+
+        Ok(lut)
+    }
+}
+
+fn decode_tans(src: &[u8], out: &mut [u8]) -> io::Result<usize> {
+    ensure(src.len() >= 8 && out.len() >= 5, "invalid tans block")?;
+    let mut br = BitReader::new(src);
+    // reserved
+    ensure(br.read_bits(1) == 0, "invalid tans block")?;
+    let l_bits = br.read_bits(2) + 8;
+    let table = TansTable::read(&mut br, l_bits)?;
+    let lut = table.lut(l_bits)?;
+
+    let pos = br.position();
+    ensure(pos >= 0 && (pos as usize) < src.len(), "truncated tans block")?;
+    let mut p = pos;
+    let end = src.len() as isize - 4;
+    let l_mask = (1 << l_bits) - 1;
+    let mut states = [0u32; 5];
+
+    // initial states
+    let mut bits_f = le32_at(src, p);
+    p += 4;
+    let mut bits_b = le32_at(src, end).swap_bytes();
+    let mut bitpos_f = 32i32;
+    let mut bitpos_b = 32i32;
+    for i in 0..2 {
+        states[2 * i] = bits_f & l_mask;
+        states[2 * i + 1] = bits_b & l_mask;
+        bits_f >>= l_bits;
+        bitpos_f -= l_bits as i32;
+        bits_b >>= l_bits;
+        bitpos_b -= l_bits as i32;
+    }
+    bits_f |= le32_at(src, p) << bitpos_f;
+    p += ((31 - bitpos_f) >> 3) as isize;
+    bitpos_f |= 24;
+    states[4] = bits_f & l_mask;
+    bits_f >>= l_bits;
+    bitpos_f -= l_bits as i32;
+
+    let mut ptr_f = p - (bitpos_f >> 3) as isize;
+    bitpos_f &= 7;
+    let mut ptr_b = end + (bitpos_b >> 3) as isize;
+    bitpos_b &= 7;
+    ensure(ptr_f <= ptr_b, "invalid tans block")?;
+
+    let d_end = out.len() - 5;
+    let mut d = 0;
+    'decode: while d < d_end {
+        for backwards in [false, true] {
+            for (i, state) in states.iter_mut().enumerate() {
+                if i % 2 == 0 {
+                    if backwards {
+                        bits_b |= le32_at(src, ptr_b - 4).swap_bytes() << bitpos_b;
+                        ptr_b -= ((31 - bitpos_b) >> 3) as isize;
+                        bitpos_b |= 24;
+                    } else {
+                        bits_f |= le32_at(src, ptr_f) << bitpos_f;
+                        ptr_f += ((31 - bitpos_f) >> 3) as isize;
+                        bitpos_f |= 24;
+                    }
+                }
+
+                let e = *lut.get(*state as usize).ok_or_else(|| corrupt("invalid tans state"))?;
+                out[d] = e.symbol;
+                d += 1;
+                let (bits, bitpos) = if backwards {
+                    (&mut bits_b, &mut bitpos_b)
+                } else {
+                    (&mut bits_f, &mut bitpos_f)
+                };
+                *bitpos -= e.bits_x as i32;
+                *state = (*bits & e.x) + e.w;
+                *bits >>= e.bits_x;
+                if d >= d_end {
+                    break 'decode;
+                }
+            }
+        }
+    }
+
+    ensure(ptr_b - ptr_f + (bitpos_f >> 3) as isize + (bitpos_b >> 3) as isize == 0, "tans block size mismatch")?;
+    ensure(states.iter().all(|&s| s <= 0xFF), "invalid tans state")?;
+    for (i, state) in states.into_iter().enumerate() {
+        out[d_end + i] = state as u8;
+    }
+
+    Ok(src.len())
+}
+
+fn decode_rle(src: &[u8], out: &mut [u8]) -> io::Result<usize> {
+    if src.len() <= 1 {
+        ensure(src.len() == 1, "invalid rle block")?;
+        out.fill(src[0]);
+        return Ok(1);
+    }
+
+    // commands are read from the end while data is read from the start
+    let cmds = if src[0] != 0 {
+        let (mut cmds, n) = decode_bytes(src, SCRATCH_SIZE)?;
+        cmds.extend_from_slice(&src[n..]);
+        Cow::Owned(cmds)
+    } else {
+        Cow::Borrowed(&src[1..])
+    };
+    let le16 = |i: usize| cmds[i] as u32 | (cmds[i + 1] as u32) << 8;
+
+    let mut ptr = 0;
+    let mut end = cmds.len();
+    let mut d = 0;
+    let mut rle_byte = 0;
+    while ptr < end {
+        let cmd = cmds[end - 1] as u32;
+        let (copy, rle) = if cmd.wrapping_sub(1) >= 0x2F {
+            end -= 1;
+            ((!cmd & 0xF) as usize, (cmd >> 4) as usize)
+        } else if cmd >= 0x10 {
+            ensure(end - ptr >= 2, "truncated rle block")?;
+            let data = le16(end - 2).wrapping_sub(4096);
+            end -= 2;
+            ((data & 0x3F) as usize, (data >> 6) as usize)
+        } else if cmd == 1 {
+            ensure(end - ptr >= 2, "truncated rle block")?;
+            rle_byte = cmds[ptr];
+            ptr += 1;
+            end -= 1;
+            continue;
+        } else if cmd >= 9 {
+            ensure(end - ptr >= 2, "truncated rle block")?;
+            let rle = le16(end - 2).wrapping_sub(0x8FF).wrapping_mul(128);
+            end -= 2;
+            (0, rle as usize)
+        } else {
+            ensure(end - ptr >= 2, "truncated rle block")?;
+            let copy = le16(end - 2).wrapping_sub(511).wrapping_mul(64);
+            end -= 2;
+            (copy as usize, 0)
+        };
+
+        ensure(out.len() - d >= copy + rle && end - ptr >= copy, "invalid rle block")?;
+        out[d..d + copy].copy_from_slice(&cmds[ptr..ptr + copy]);
+        ptr += copy;
+        d += copy;
+        out[d..d + rle].fill(rle_byte);
+        d += rle;
+    }
+    ensure(ptr == end && d == out.len(), "rle block size mismatch")?;
+
+    Ok(src.len())
+}
+
+fn decode_recursive(src: &[u8], out: &mut [u8]) -> io::Result<usize> {
+    ensure(src.len() >= 6, "truncated recursive block")?;
+    let n = src[0] & 0x7F;
+    ensure(n >= 2, "invalid recursive block")?;
+
+    if src[0] & 0x80 == 0 {
+        let mut pos = 1;
+        let mut d = 0;
+        for _ in 0..n {
+            let (data, used) = decode_bytes(&src[pos..], out.len() - d)?;
+            out[d..d + data.len()].copy_from_slice(&data);
+            d += data.len();
+            pos += used;
+        }
+        ensure(d == out.len(), "recursive block size mismatch")?;
+        Ok(pos)
+    } else {
+        let (arrays, used) = decode_multi_array(src, out.len(), 1)?;
+        ensure(arrays[0].len() == out.len(), "recursive block size mismatch")?;
+        out.copy_from_slice(&arrays[0]);
+        Ok(used)
+    }
+}
+
+fn decode_multi_array(src: &[u8], capacity: usize, array_count: usize) -> io::Result<(Vec<Vec<u8>>, usize)> {
+    ensure(src.len() >= 4, "truncated multi array")?;
+    ensure(src[0] & 0x80 != 0, "invalid multi array")?;
+    let num_arrays = (src[0] & 0x3F) as usize;
+    let mut pos = 1;
+
+    if num_arrays == 0 {
+        let mut arrays = Vec::with_capacity(array_count);
+        let mut total = 0;
+        for _ in 0..array_count {
+            let (data, n) = decode_bytes(&src[pos..], capacity - total)?;
+            total += data.len();
+            pos += n;
+            arrays.push(data);
+        }
+        return Ok((arrays, pos));
+    }
+
+    let mut entropy: Vec<Cow<[u8]>> = Vec::with_capacity(num_arrays);
+    let mut total = 0;
+    for _ in 0..num_arrays {
+        let (data, n) = decode_bytes(&src[pos..], SCRATCH_SIZE.saturating_sub(total))?;
+        total += data.len();
+        pos += n;
+        entropy.push(Cow::Owned(data));
+    }
+
+    ensure(src.len() - pos >= 3, "truncated multi array")?;
+    let q = le16_at(src, pos as isize);
+    pos += 2;
+
+    let num_indexes = BytesHeader::parse(&src[pos..], total)?.dst_size;
+    ensure(num_indexes > array_count, "invalid multi array")?;
+    let mut num_lens = num_indexes - array_count;
+
+    let (lenlog2, indexes) = if q & 0x8000 != 0 {
+        let (data, n) = decode_bytes(&src[pos..], num_indexes)?;
+        ensure(data.len() == num_indexes, "invalid multi array")?;
+        pos += n;
+        num_lens = num_indexes;
+        (data.iter().map(|t| t >> 4).collect(), data.iter().map(|t| t & 0xF).collect())
+    } else {
+        let (indexes, n) = decode_bytes(&src[pos..], num_indexes)?;
+        ensure(indexes.len() == num_indexes, "invalid multi array")?;
+        pos += n;
+        let (lenlog2, n) = decode_bytes(&src[pos..], num_indexes)?;
+        ensure(lenlog2.len() == num_lens, "invalid multi array")?;
+        pos += n;
+        ensure(lenlog2.iter().all(|&v| v <= 16), "invalid multi array")?;
+        (lenlog2, indexes)
+    };
+
+    // interval lengths are read from both ends
+    let varbits_len = (q & 0x3FFF) as usize;
+    ensure(src.len() - pos >= varbits_len, "truncated multi array")?;
+    let end = pos + varbits_len;
+    let mut f = pos as isize;
+    let mut bits_f = 0u32;
+    let mut bitpos_f = 24i32;
+    let mut b = end as isize;
+    let mut bits_b = 0u32;
+    let mut bitpos_b = 24i32;
+    let mask = |n: u8| (2u32 << n) - 1;
+
+    let mut intervals = vec![0u32; num_lens];
+    let mut i = 0;
+    while i + 2 <= num_lens {
+        bits_f |= shr(le32_at(src, f).swap_bytes(), (24 - bitpos_f) as u32);
+        f += ((bitpos_f + 7) >> 3) as isize;
+        bits_b |= shr(le32_at(src, b - 4), (24 - bitpos_b) as u32);
+        b -= ((bitpos_b + 7) >> 3) as isize;
+
+        let numbits_f = lenlog2[i];
+        let numbits_b = lenlog2[i + 1];
+        bits_f = (bits_f | 1).rotate_left(numbits_f as u32);
+        bitpos_f += numbits_f as i32 - 8 * ((bitpos_f + 7) >> 3);
+        bits_b = (bits_b | 1).rotate_left(numbits_b as u32);
+        bitpos_b += numbits_b as i32 - 8 * ((bitpos_b + 7) >> 3);
+
+        intervals[i] = bits_f & mask(numbits_f);
+        bits_f &= !mask(numbits_f);
+        intervals[i + 1] = bits_b & mask(numbits_b);
+        bits_b &= !mask(numbits_b);
+        i += 2;
+    }
+    if i < num_lens {
+        bits_f |= shr(le32_at(src, f).swap_bytes(), (24 - bitpos_f) as u32);
+        let numbits_f = lenlog2[i];
+        bits_f = (bits_f | 1).rotate_left(numbits_f as u32);
+        intervals[i] = bits_f & mask(numbits_f);
+    }
+
+    ensure(indexes[num_indexes - 1] == 0, "invalid multi array")?;
+
+    let mut indexes = indexes.iter();
+    let mut intervals = intervals.iter();
+    let mut arrays = Vec::with_capacity(array_count);
+    let mut written = 0;
+    for _ in 0..array_count {
+        let mut array = Vec::new();
+        loop {
+            let source = *indexes.next().ok_or_else(|| corrupt("invalid multi array"))? as usize;
+            if source == 0 {
+                break;
+            }
+            ensure(source <= num_arrays, "invalid multi array")?;
+            let len = *intervals.next().ok_or_else(|| corrupt("invalid multi array"))? as usize;
+            let data = &mut entropy[source - 1];
+            ensure(len <= data.len() && len <= capacity - written, "invalid multi array")?;
+            array.extend_from_slice(&data[..len]);
+            *data = Cow::Owned(data[len..].to_vec());
+            written += len;
+        }
+        if q & 0x8000 != 0 {
+            intervals.next();
+        }
+        arrays.push(array);
+    }
+    ensure(indexes.len() == 0 && intervals.len() == 0, "invalid multi array")?;
+    ensure(entropy.iter().all(|data| data.is_empty()), "invalid multi array")?;
+
+    Ok((arrays, end))
+}
+
+// Copy `len` bytes from `from` one byte at a time so overlapping matches
+// repeat their pattern.
+fn copy_match(out: &mut [u8], dst: usize, from: usize, len: usize) {
+    if dst - from >= len {
+        out.copy_within(from..from + len, dst);
+    } else {
+        for i in 0..len {
+            out[dst + i] = out[from + i];
+        }
+    }
+}
+
+// Mode 0 adds literals to the byte at the last match offset while mode 1
+// copies them.
+fn copy_literals(out: &mut [u8], dst: usize, lits: &[u8], mode: u32, last_offset: isize) -> io::Result<()> {
+    if mode == 0 {
+        let back = last_offset.unsigned_abs();
+        ensure(back <= dst, "invalid match offset")?;
+        for (i, &lit) in lits.iter().enumerate() {
+            out[dst + i] = lit.wrapping_add(out[dst + i - back]);
+        }
+    } else {
+        out[dst..dst + lits.len()].copy_from_slice(lits);
+    }
+    Ok(())
+}
+
+struct KrakenLzTable {
+    // literal length, match length and recent offset index of every run
+    cmd_stream: Vec<u8>,
+    offs_stream: Vec<i32>,
+    lit_stream: Vec<u8>,
+    // lengths that do not fit in the commands
+    len_stream: Vec<u32>,
+}
+
+impl KrakenLzTable {
+    fn read(mode: u32, src: &[u8], out: &mut [u8], dst: usize, dst_size: usize) -> io::Result<Self> {
+        ensure(mode <= 1, "invalid chunk mode")?;
+        ensure(src.len() >= 13, "truncated lz table")?;
+
+        let mut pos = 0;
+        if dst == 0 {
+            ensure(dst_size >= 8, "truncated lz table")?;
+            out[..8].copy_from_slice(&src[..8]);
+            pos = 8;
+        }
+        ensure(src[pos] & 0x80 == 0, "excess bytes are not supported")?;
+
+        let (lit_stream, n) = decode_bytes(&src[pos..], dst_size.min(SCRATCH_SIZE))?;
+        pos += n;
+        let (cmd_stream, n) = decode_bytes(&src[pos..], dst_size.min(SCRATCH_SIZE))?;
+        pos += n;
+
+        ensure(src.len() - pos >= 3, "truncated lz table")?;
+        let mut offs_scaling = 0;
+        let mut offs_extra = None;
+        if src[pos] & 0x80 != 0 {
+            // offsets are coded with two tables
+            offs_scaling = src[pos] as i32 - 127;
+            pos += 1;
+        }
+        let (packed_offs, n) = decode_bytes(&src[pos..], cmd_stream.len())?;
+        pos += n;
+        if offs_scaling > 1 {
+            let (extra, n) = decode_bytes(&src[pos..], packed_offs.len())?;
+            ensure(extra.len() == packed_offs.len(), "invalid lz table")?;
+            pos += n;
+            offs_extra = Some(extra);
+        }
+        let (packed_lens, n) = decode_bytes(&src[pos..], dst_size >> 2)?;
+        pos += n;
+
+        let (offs_stream, len_stream) = unpack_offsets(
+            &src[pos..],
+            &packed_offs,
+            offs_extra.as_deref(),
+            offs_scaling,
+            &packed_lens,
+        )?;
+
+        Ok(Self {
+            cmd_stream,
+            offs_stream,
+            lit_stream,
+            len_stream,
+        })
+    }
+
+    fn process_runs(&self, mode: u32, out: &mut [u8], start: usize, end: usize) -> io::Result<()> {
+        let mut dst = if start == 0 { 8 } else { start };
+        let mut lits = &self.lit_stream[..];
+        let mut lens = self.len_stream.iter();
+        let mut offs = self.offs_stream.iter();
+        let mut recent_offs = [0, 0, 0, -8, -8, -8, 0];
+        let mut last_offset = -8isize;
+
+        for &f in &self.cmd_stream {
+            let mut litlen = (f & 3) as usize;
+            let offs_index = (f >> 6) as usize;
+            let matchlen = ((f >> 2) & 0xF) as usize;
+
+            if litlen == 3 {
+                litlen = *lens.next().ok_or_else(|| corrupt("truncated length stream"))? as usize;
+            }
+            ensure(litlen <= lits.len() && litlen <= end - dst, "invalid literal length")?;
+            copy_literals(out, dst, &lits[..litlen], mode, last_offset)?;
+            dst += litlen;
+            lits = &lits[litlen..];
+
+            if offs_index == 3 {
+                recent_offs[6] = *offs.next().ok_or_else(|| corrupt("truncated offset stream"))?;
+            }
+            let offset = recent_offs[offs_index + 3];
+            recent_offs[offs_index + 3] = recent_offs[offs_index + 2];
+            recent_offs[offs_index + 2] = recent_offs[offs_index + 1];
+            recent_offs[offs_index + 1] = recent_offs[offs_index];
+            recent_offs[3] = offset;
+            last_offset = offset as isize;
+
+            ensure(offset < 0 && offset.unsigned_abs() as usize <= dst, "invalid match offset")?;
+            let from = dst - offset.unsigned_abs() as usize;
+            let len = if matchlen != 15 {
+                matchlen + 2
+            } else {
+                14 + *lens.next().ok_or_else(|| corrupt("truncated length stream"))? as usize
+            };
+            ensure(len <= end - dst, "invalid match length")?;
+            copy_match(out, dst, from, len);
+            dst += len;
+        }
+
+        ensure(offs.len() == 0 && lens.len() == 0, "lz table size mismatch")?;
+        ensure(lits.len() == end - dst, "lz table size mismatch")?;
+        copy_literals(out, dst, lits, mode, last_offset)
+    }
+}
+
+fn unpack_offsets(
+    src: &[u8],
+    packed_offs: &[u8],
+    offs_extra: Option<&[u8]>,
+    offs_scaling: i32,
+    packed_lens: &[u8],
+) -> io::Result<(Vec<i32>, Vec<u32>)> {
+    let mut a = BitReader::new(src);
+    let mut b = BitReader::new_backwards(src);
+
+    ensure(b.bits >= 0x2000, "invalid offset stream")?;
+    let n = b.bits.leading_zeros();
+    b.bitpos += n as i32;
+    b.bits <<= n;
+    b.refill();
+    let n = n + 1;
+    let u32_len_count = ((b.bits >> (32 - n)) - 1) as usize;
+    b.bitpos += n as i32;
+    b.bits <<= n;
+    b.refill();
+
+    let mut offs = Vec::with_capacity(packed_offs.len());
+    if offs_scaling == 0 {
+        for (i, &v) in packed_offs.iter().enumerate() {
+            let br = if i % 2 == 0 { &mut a } else { &mut b };
+            offs.push(-(br.read_distance(v as u32) as i32));
+        }
+    } else {
+        for (i, &cmd) in packed_offs.iter().enumerate() {
+            let br = if i % 2 == 0 { &mut a } else { &mut b };
+            let cmd = cmd as u32;
+            ensure(cmd >> 3 <= 26, "invalid offset stream")?;
+            let v = ((8 + (cmd & 7)) << (cmd >> 3)) | br.read_more_than_24_bits(cmd >> 3);
+            offs.push(8 - v as i32);
+        }
+        if let Some(extra) = offs_extra {
+            for (off, &low) in offs.iter_mut().zip(extra) {
+                *off = offs_scaling.wrapping_mul(*off).wrapping_sub(low as i32);
+            }
+        }
+    }
+
+    ensure(u32_len_count <= 512, "invalid length stream")?;
+    let mut u32_lens = Vec::with_capacity(u32_len_count);
+    for i in 0..u32_len_count {
+        let br = if i % 2 == 0 { &mut a } else { &mut b };
+        u32_lens.push(br.read_length().ok_or_else(|| corrupt("invalid length stream"))?);
+    }
+
+    a.p -= ((24 - a.bitpos) >> 3) as isize;
+    b.p += ((24 - b.bitpos) >> 3) as isize;
+    ensure(a.p == b.p, "offset stream size mismatch")?;
+
+    let mut u32_lens = u32_lens.into_iter();
+    let mut lens = Vec::with_capacity(packed_lens.len());
+    for &v in packed_lens {
+        let mut v = v as u32;
+        if v == 255 {
+            v = u32_lens.next().ok_or_else(|| corrupt("truncated length stream"))?.wrapping_add(255);
+        }
+        lens.push(v.wrapping_add(3));
+    }
+    ensure(u32_lens.len() == 0, "length stream size mismatch")?;
+
+    Ok((offs, lens))
+}
+
+struct MermaidLzTable {
+    lit_stream: Vec<u8>,
+    cmd_stream: Vec<u8>,
+    // commands of the second 64 KiB block
+    cmd_stream_2_offs: usize,
+    off16_stream: Vec<u16>,
+    off32_stream_1: Vec<u32>,
+    off32_stream_2: Vec<u32>,
+    // position in the chunk of the lengths that do not fit in the commands
+    length_stream: usize,
+}
+
+impl MermaidLzTable {
+    fn read(mode: u32, src: &[u8], out: &mut [u8], dst: usize, dst_size: usize) -> io::Result<Self> {
+        ensure(mode <= 1, "invalid chunk mode")?;
+        ensure(src.len() >= 10, "truncated lz table")?;
+
+        let mut pos = 0;
+        if dst == 0 {
+            ensure(dst_size >= 8, "truncated lz table")?;
+            out[..8].copy_from_slice(&src[..8]);
+            pos = 8;
+        }
+
+        let (lit_stream, n) = decode_bytes(&src[pos..], dst_size.min(SCRATCH_SIZE))?;
+        pos += n;
+        let (cmd_stream, n) = decode_bytes(&src[pos..], dst_size.min(SCRATCH_SIZE))?;
+        pos += n;
+
+        let cmd_stream_2_offs = if dst_size <= 0x10000 {
+            cmd_stream.len()
+        } else {
+            ensure(src.len() - pos >= 2, "truncated lz table")?;
+            let offs = le16_at(src, pos as isize) as usize;
+            pos += 2;
+            ensure(offs <= cmd_stream.len(), "invalid lz table")?;
+            offs
+        };
+
+        ensure(src.len() - pos >= 2, "truncated lz table")?;
+        let off16_count = le16_at(src, pos as isize) as usize;
+        pos += 2;
+        let off16_stream = if off16_count == 0xFFFF {
+            // entropy coded
+            let (hi, n) = decode_bytes(&src[pos..], dst_size >> 1)?;
+            pos += n;
+            let (lo, n) = decode_bytes(&src[pos..], dst_size >> 1)?;
+            pos += n;
+            ensure(lo.len() == hi.len(), "invalid lz table")?;
+            lo.iter().zip(&hi).map(|(&lo, &hi)| lo as u16 | (hi as u16) << 8).collect()
+        } else {
+            ensure(src.len() - pos >= off16_count * 2, "truncated lz table")?;
+            let off16 = src[pos..pos + off16_count * 2]
+                .chunks_exact(2)
+                .map(|v| u16::from_le_bytes([v[0], v[1]]))
+                .collect();
+            pos += off16_count * 2;
+            off16
+        };
+
+        ensure(src.len() - pos >= 3, "truncated lz table")?;
+        let tmp = le32_at(src, pos as isize) & 0xFFFFFF;
+        pos += 3;
+
+        let mut off32_stream_1 = Vec::new();
+        let mut off32_stream_2 = Vec::new();
+        if tmp != 0 {
+            let mut size_1 = (tmp >> 12) as usize;
+            let mut size_2 = (tmp & 0xFFF) as usize;
+            if size_1 == 4095 {
+                ensure(src.len() - pos >= 2, "truncated lz table")?;
+                size_1 = le16_at(src, pos as isize) as usize;
+                pos += 2;
+            }
+            if size_2 == 4095 {
+                ensure(src.len() - pos >= 2, "truncated lz table")?;
+                size_2 = le16_at(src, pos as isize) as usize;
+                pos += 2;
+            }
+
+            let n;
+            (off32_stream_1, n) = decode_far_offsets(&src[pos..], size_1, dst)?;
+            pos += n;
+            let n;
+            (off32_stream_2, n) = decode_far_offsets(&src[pos..], size_2, dst + 0x10000)?;
+            pos += n;
+        }
+
+        Ok(Self {
+            lit_stream,
+            cmd_stream,
+            cmd_stream_2_offs,
+            off16_stream,
+            off32_stream_1,
+            off32_stream_2,
+            length_stream: pos,
+        })
+    }
+
+    fn process_runs(&self, mode: u32, src: &[u8], out: &mut [u8], start: usize, size: usize) -> io::Result<()> {
+        let mut state = MermaidState {
+            src,
+            lits: &self.lit_stream,
+            off16: self.off16_stream.iter(),
+            length_stream: self.length_stream,
+            recent_offs: -8,
+        };
+
+        let first = size.min(0x10000);
+        let startoff = if start == 0 { 8 } else { 0 };
+        state.block(
+            mode,
+            out,
+            start,
+            first,
+            startoff,
+            &self.cmd_stream[..self.cmd_stream_2_offs],
+            &self.off32_stream_1,
+        )?;
+        if size > first {
+            state.block(
+                mode,
+                out,
+                start + first,
+                size - first,
+                0,
+                &self.cmd_stream[self.cmd_stream_2_offs..],
+                &self.off32_stream_2,
+            )?;
+        }
+
+        ensure(state.length_stream == src.len(), "lz table size mismatch")
+    }
+}
+
+// Read offsets relative to the start of a 64 KiB block at `offset`.
+fn decode_far_offsets(src: &[u8], count: usize, offset: usize) -> io::Result<(Vec<u32>, usize)> {
+    let mut offsets = Vec::with_capacity(count);
+    let mut pos = 0;
+    for _ in 0..count {
+        ensure(src.len() - pos >= 3, "truncated far offsets")?;
+        let mut off = be24(&[src[pos + 2], src[pos + 1], src[pos]]);
+        pos += 3;
+        if offset >= 0xC00000 - 1 && off >= 0xC00000 {
+            ensure(pos < src.len(), "truncated far offsets")?;
+            off += (src[pos] as u32) << 22;
+            pos += 1;
+        }
+        ensure(off as usize <= offset, "invalid far offset")?;
+        offsets.push(off);
+    }
+    Ok((offsets, pos))
+}
+
+struct MermaidState<'a> {
+    src: &'a [u8],
+    lits: &'a [u8],
+    off16: std::slice::Iter<'a, u16>,
+    length_stream: usize,
+    recent_offs: isize,
+}
+
+impl MermaidState<'_> {
+    fn read_length(&mut self) -> io::Result<usize> {
+        let pos = self.length_stream;
+        ensure(pos < self.src.len(), "truncated length stream")?;
+        let mut length = self.src[pos] as usize;
+        if length > 251 {
+            ensure(self.src.len() - pos >= 3, "truncated length stream")?;
+            length += le16_at(self.src, pos as isize + 1) as usize * 4;
+            self.length_stream += 2;
+        }
+        self.length_stream += 1;
+        Ok(length)
+    }
+
+    fn literals(&mut self, mode: u32, out: &mut [u8], dst: usize, len: usize) -> io::Result<()> {
+        ensure(len <= self.lits.len(), "truncated literal stream")?;
+        copy_literals(out, dst, &self.lits[..len], mode, self.recent_offs)?;
+        self.lits = &self.lits[len..];
+        Ok(())
+    }
+
+    fn next_off16(&mut self) -> io::Result<usize> {
+        Ok(*self.off16.next().ok_or_else(|| corrupt("truncated offset stream"))? as usize)
+    }
+
+    // copy a match from `back` bytes before `dst`
+    fn copy(&mut self, out: &mut [u8], dst: usize, back: usize, len: usize, end: usize) -> io::Result<()> {
+        ensure(back <= dst, "invalid match offset")?;
+        ensure(len <= end - dst, "invalid match length")?;
+        self.recent_offs = -(back as isize);
+        copy_match(out, dst, dst - back, len);
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn block(
+        &mut self,
+        mode: u32,
+        out: &mut [u8],
+        begin: usize,
+        size: usize,
+        startoff: usize,
+        cmds: &[u8],
+        off32: &[u32],
+    ) -> io::Result<()> {
+        let end = begin + size;
+        let mut dst = begin + startoff;
+        let mut off32 = off32.iter();
+        let mut next_off32 = || {
+            off32.next()
+                .map(|&off| off as usize)
+                .ok_or_else(|| corrupt("truncated offset stream"))
+        };
+
+        for &cmd in cmds {
+            if cmd >= 24 {
+                let litlen = (cmd & 7) as usize;
+                ensure(litlen <= end - dst, "invalid literal length")?;
+                self.literals(mode, out, dst, litlen)?;
+                dst += litlen;
+
+                let back = if cmd < 128 {
+                    self.next_off16()?
+                } else {
+                    self.recent_offs.unsigned_abs()
+                };
+                let len = ((cmd >> 3) & 0xF) as usize;
+                self.copy(out, dst, back, len, end)?;
+                dst += len;
+            } else if cmd > 2 {
+                let len = cmd as usize + 5;
+                let back = dst - begin + next_off32()?;
+                self.copy(out, dst, back, len, end)?;
+                dst += len;
+            } else if cmd == 0 {
+                let len = self.read_length()? + 64;
+                ensure(len <= end - dst, "invalid literal length")?;
+                self.literals(mode, out, dst, len)?;
+                dst += len;
+            } else if cmd == 1 {
+                let len = self.read_length()? + 91;
+                let back = self.next_off16()?;
+                self.copy(out, dst, back, len, end)?;
+                dst += len;
+            } else {
+                let len = self.read_length()? + 29;
+                let back = dst - begin + next_off32()?;
+                self.copy(out, dst, back, len, end)?;
+                dst += len;
+            }
+        }
+
+        self.literals(mode, out, dst, end - dst)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::path::PathBuf;
+    use super::*;
+
+    #[test]
+    fn uncompressed_block() {
+        let mut src = vec![0x4C, 0x06];
+        src.extend(0..100u8);
+        let mut out = [0; 100];
+        assert_eq!(src.len(), decompress(&src, &mut out).unwrap());
+        assert!(out.iter().copied().eq(0..100u8));
+    }
+
+    #[test]
+    fn memset_quantum() {
+        let src = [0x0C, 0x0A, 0x07, 0xFF, 0xFF, 0xAB];
+        let mut out = [0; 0x100];
+        assert_eq!(src.len(), decompress(&src, &mut out).unwrap());
+        assert!(out.iter().all(|&b| b == 0xAB));
+    }
+
+    #[test]
+    fn lz_chunk() {
+        let src = [
+            // block header, quantum of 24 bytes
+            0x0C, 0x06, 0x00, 0x00, 0x17,
+            // chunk with raw literals
+            0x88, 0x00, 0x15,
+            // first 8 bytes
+            b'a', b'b', b'c', b'd', b'e', b'f', b'g', b'h',
+            // literals
+            0x00, 0x00, 0x00,
+            // commands matching 16 and 8 bytes at the initial offset
+            0x80, 0x02, 0x38, 0x18,
+            // offsets
+            0x00, 0x00, 0x00,
+            // lengths
+            0x80, 0x00,
+            // no long lengths
+            0x80,
+        ];
+        let mut out = [0; 32];
+        assert_eq!(src.len(), decompress(&src, &mut out).unwrap());
+        assert_eq!(&out, b"abcdefghabcdefghabcdefghabcdefgh");
+    }
+
+    #[test]
+    fn unsupported_decoder() {
+        let src = [0x0C, 0x0C, 0x00, 0x00, 0x00];
+        let mut out = [0; 0x10];
+        assert!(decompress(&src, &mut out).is_err());
+    }
+
+    // Decompress every `<name>.compressed` in `dir` and compare it with
+    // `<name>.raw`. Returns the number of pairs.
+    fn check_chunk_pairs(dir: &Path) -> usize {
+        let mut count = 0;
+        for fd in fs::read_dir(dir).unwrap() {
+            let path = fd.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "compressed") {
+                continue;
+            }
+
+            let src = fs::read(&path).unwrap();
+            let raw = fs::read(path.with_extension("raw")).unwrap();
+            let mut out = vec![0; raw.len()];
+            decompress(&src, &mut out).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+            assert!(out == raw, "{} decompressed incorrectly", path.display());
+            count += 1;
+        }
+        count
+    }
+
+    // The pairs in tests/chunks were encoded by hand with stored entropy
+    // blocks and only cover the LZ decoders.
+    #[test]
+    fn encoded_chunks() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/chunks");
+        assert!(check_chunk_pairs(&dir) >= 2, "no chunks in {}", dir.display());
+    }
+
+    // Pairs recorded from the Oodle library, such as the ones written by
+    // oodle_cross_check, in the directory in LIMN_CHUNK_PAIRS.
+    #[test]
+    #[ignore = "needs chunks recorded from Oodle in LIMN_CHUNK_PAIRS"]
+    fn recorded_chunks() {
+        let dir = env::var_os("LIMN_CHUNK_PAIRS")
+            .map(PathBuf::from)
+            .expect("LIMN_CHUNK_PAIRS is not set");
+        assert!(check_chunk_pairs(&dir) > 0, "no chunks in {}", dir.display());
+    }
+
+    // Setting LIMN_RECORD_CHUNKS to a directory writes small pairs there for
+    // recorded_chunks.
+    #[test]
+    #[ignore = "needs the Oodle library"]
+    fn oodle_cross_check() {
+        use crate::oodle::Oodle;
+        use crate::oodle::OodleCompressor;
+        use crate::oodle::OodleLevel;
+        use crate::oodle::LIBRARY_NAMES;
+
+        let paths = env::var_os("LIMN_OODLE")
+            .map(PathBuf::from)
+            .into_iter()
+            .chain(LIBRARY_NAMES.iter().map(PathBuf::from));
+        let oodle = Oodle::load_first(paths).unwrap();
+
+        let data = crate::oodle::sample();
+
+        let record = env::var_os("LIMN_RECORD_CHUNKS").map(PathBuf::from);
+        for compressor in [OodleCompressor::Kraken, OodleCompressor::Mermaid] {
+            for level in [OodleLevel::SuperFast, OodleLevel::Normal, OodleLevel::Optimal2] {
+                let mut compressed = vec![0; oodle.compressed_size_needed(compressor, data.len()).unwrap()];
+                let size = oodle.compress(compressor, level, &data, &mut compressed).unwrap();
+                let mut out = vec![0; data.len()];
+                decompress(&compressed[..size], &mut out)
+                    .unwrap_or_else(|e| panic!("{compressor:?} {level:?}: {e}"));
+                assert!(out == data, "{compressor:?} {level:?} decompressed incorrectly");
+
+                if let Some(dir) = &record {
+                    let raw = &data[..0x10000];
+                    let size = oodle.compress(compressor, level, raw, &mut compressed).unwrap();
+                    let name = format!("{compressor:?}_{level:?}").to_lowercase();
+                    fs::write(dir.join(format!("{name}.compressed")), &compressed[..size]).unwrap();
+                    fs::write(dir.join(format!("{name}.raw")), raw).unwrap();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,340 @@
+//! Reader for the bundle format used in the game Warhammer 40k Darktide.
+//!
+//! A bundle is opened with [`BundleFd`]. Its index can be listed without
+//! decompressing anything through [`BundleFd::index`] while the file contents
+//! are streamed in order through [`BundleFd::files`]. Entries can be passed to
+//! [`file::extract`] to convert them with the matching [`file::Extractor`].
+#![feature(lazy_cell)]
+use std::collections::HashMap;
+use std::io::Read;
+use std::io::Seek;
+use std::path::Path;
+use std::sync::Mutex;
+
+pub mod bundle;
+pub mod crack;
+pub mod decompress;
+pub mod dictionary;
+pub mod duplicates;
+pub mod error;
+pub mod file;
+pub mod filter;
+pub mod harvest;
+pub mod hash;
+pub mod hashes;
+pub mod info;
+#[cfg(feature = "kraken")]
+pub mod kraken;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod oodle;
+mod placeholder;
+pub mod read;
+pub mod scoped_fs;
+pub mod writer;
+
+pub use bundle::BundleFd;
+pub use bundle::ChunkPool;
+pub use bundle::ChunkTable;
+pub use bundle::Entry;
+pub use bundle::EntryOffset;
+pub use bundle::FilesIter;
+pub use bundle::IndexEntry;
+pub use bundle::IndexIter;
+pub use bundle::Variant;
+pub use decompress::Decompressor;
+pub use dictionary::Dictionary;
+pub use error::Error;
+pub use error::Result;
+pub use file::ExtractOptions;
+pub use file::Pool;
+pub use filter::Filter;
+pub use hash::MurmurHash;
+pub use hash::MurmurHash32;
+#[cfg(feature = "kraken")]
+pub use kraken::Kraken;
+pub use oodle::Oodle;
+pub use writer::BundleWriter;
+
+/// Extract the files of a bundle with `options`.
+///
+/// `duplicates` is shared between bundles to count every `(ext, name)` pair
+/// seen. When `filter` is set only files it matches are extracted. Files in
+/// several bundles are extracted from their owner in
+/// [`ExtractOptions::owners`], or when there is none and `filter` is set from
+/// the first bundle a pair was seen in. With [`ExtractOptions::conflicts`] the
+/// files in several bundles are hashed instead of extracting anything.
+///
+/// Returns the number of files extracted. Errors from extracting single files
+/// are skipped while errors from parsing the bundle stop the extraction.
+pub fn extract_bundle(
+    pool: &mut Pool,
+    mut rdr: impl Read + Seek,
+    bundle_buf: &mut Vec<u8>,
+    bundle_hash: Option<u64>,
+    duplicates: &Mutex<HashMap<(u64, u64), u64>>,
+    options: &ExtractOptions<'_>,
+    filter: Option<&Filter>,
+) -> Result<u32> {
+    let mut bundle = BundleFd::new(bundle_hash, &mut rdr)?;
+    extract_bundle_fd(pool, &mut bundle, bundle_buf, duplicates, options, filter)
+}
+
+/// Same as [`extract_bundle`] for a bundle that is already open such as one
+/// from [`BundleFd::from_bytes`].
+pub fn extract_bundle_fd<'a>(
+    pool: &mut Pool,
+    bundle: &mut BundleFd<'a>,
+    bundle_buf: &mut Vec<u8>,
+    duplicates: &Mutex<HashMap<(u64, u64), u64>>,
+    options: &ExtractOptions<'a>,
+    filter: Option<&Filter>,
+) -> Result<u32> {
+    bundle_buf.clear();
+    // streams can not seek back to the index once it was read
+    let index = bundle.index()?.collect::<Vec<_>>();
+    let mut unknown = HashMap::new();
+    for file in &index {
+        if hash::extension_name(file.ext).is_none()
+            && !options.dictionary.contains_key(&MurmurHash::from(file.ext))
+        {
+            *unknown.entry(file.ext).or_insert(0) += 1;
+        }
+    }
+    if !unknown.is_empty() {
+        let mut total = options.unknown_extensions.lock().unwrap();
+        for (ext, count) in unknown {
+            total.entry(ext).or_default().add(count, bundle.name);
+        }
+    }
+
+    if let Some(conflicts) = options.conflicts {
+        return hash_copies(bundle, &index, bundle_buf, options, filter, conflicts);
+    }
+
+    let targets = if filter.is_some() || options.owners.is_some() {
+        let mut targets = Vec::new();
+        let mut skipped = HashMap::new();
+        let bundle_hash = bundle.name;
+        let mut dupes = duplicates.lock().unwrap();
+        for file in &index {
+            let key = (file.ext, file.name);
+            let entry = dupes.entry(key).or_insert(0);
+            *entry += 1;
+
+            let first = match options.owners {
+                Some(owners) => bundle_hash.is_some()
+                    && owners.owner(file.ext, file.name) == bundle_hash,
+                None => *entry == 1,
+            };
+            if first && filter.is_none_or(|f| f.matches(file.ext, file.name, options.dictionary)) {
+                if options.skip_unknown
+                    && !keep_unnamed(file.ext, filter)
+                    && !options.dictionary.contains_key(&MurmurHash::from(file.name))
+                {
+                    *skipped.entry(file.ext).or_insert(0) += 1;
+                    continue;
+                }
+                targets.push((file.ext, file.name));
+            }
+        }
+        drop(dupes);
+        add_skipped(options, skipped);
+
+        if targets.is_empty() {
+            return Ok(0);
+        } else {
+            Some(targets)
+        }
+    } else {
+        None
+    };
+
+    if options.skip_extract {
+        return Ok(targets.as_ref().map(|t| t.len() as u32).unwrap_or(0));
+    }
+
+    let mut targets = targets.as_ref().map(|t| &t[..]);
+    let mut count = 0;
+    let mut skipped = HashMap::new();
+    if let Some(pool) = options.chunk_pool {
+        bundle.set_prefetch(pool, options.prefetch);
+    }
+    let mut files = bundle.files(options.oodle, bundle_buf)?;
+    while let Some(file) = files.next_file()? {
+        if options.skip_unknown
+            && !keep_unnamed(file.ext, filter)
+            && !options.dictionary.contains_key(&MurmurHash::from(file.name))
+        {
+            // only targets are extracted and those were counted above
+            if targets.is_none() {
+                *skipped.entry(file.ext).or_insert(0) += 1;
+            }
+            continue;
+        }
+
+        if let Some(targets) = &mut targets {
+            let (ext, name) = targets.first().unwrap();
+            if *ext == file.ext && *name == file.name {
+                (_, *targets) = targets.split_at(1);
+            } else {
+                continue;
+            }
+        }
+
+        match file::extract(file, pool, options) {
+            Ok(_wrote) => count += 1,
+            Err(_e) => (),//eprintln!("{e}"),
+        }
+
+        if let Some(targets) = &targets {
+            if targets.is_empty() {
+                break;
+            }
+        }
+    }
+    add_skipped(options, skipped);
+
+    Ok(count)
+}
+
+// hash the files that have copies in other bundles
+fn hash_copies<'a>(
+    bundle: &mut BundleFd<'a>,
+    index: &[IndexEntry],
+    bundle_buf: &mut Vec<u8>,
+    options: &ExtractOptions<'a>,
+    filter: Option<&Filter>,
+    conflicts: &Mutex<duplicates::Conflicts>,
+) -> Result<u32> {
+    let (Some(owners), Some(bundle_hash)) = (options.owners, bundle.name) else {
+        return Ok(0);
+    };
+
+    let targets = index.iter()
+        .map(|file| (file.ext, file.name))
+        .filter(|&(ext, name)| owners.copies(ext, name) > 1
+            && filter.is_none_or(|f| f.matches(ext, name, options.dictionary)))
+        .collect::<Vec<_>>();
+    if targets.is_empty() {
+        return Ok(0);
+    }
+
+    let mut targets = &targets[..];
+    let mut hashes = Vec::with_capacity(targets.len());
+    let mut content = Vec::new();
+    if let Some(pool) = options.chunk_pool {
+        bundle.set_prefetch(pool, options.prefetch);
+    }
+    let mut files = bundle.files(options.oodle, bundle_buf)?;
+    while let Some(mut file) = files.next_file()? {
+        if targets.first() != Some(&(file.ext, file.name)) {
+            continue;
+        }
+        targets = &targets[1..];
+
+        let hash = duplicates::content_hash(&mut file, &mut content)
+            .map_err(|e| Error::from_io(Some(bundle_hash), 0, e))?;
+        hashes.push(((file.ext, file.name), hash));
+        if targets.is_empty() {
+            break;
+        }
+    }
+
+    let count = hashes.len() as u32;
+    conflicts.lock().unwrap().add(bundle_hash, hashes);
+    Ok(count)
+}
+
+// files extracted without a name even with skip_unknown
+fn keep_unnamed(ext: u64, filter: Option<&Filter>) -> bool {
+    ext == /*lua*/0xa14e8dfa2cd117e2
+        || (ext == /*strings*/0x0d972bab10b40fd3 && filter.is_some_and(|f| f.includes_ext(ext)))
+}
+
+fn add_skipped(options: &ExtractOptions<'_>, skipped: HashMap<u64, u64>) {
+    if skipped.is_empty() {
+        return;
+    }
+    let mut total = options.skipped_unknown.lock().unwrap();
+    for (ext, count) in skipped {
+        *total.entry(ext).or_insert(0) += count;
+    }
+}
+
+/// Parse the bundle hash from the file name of a bundle.
+pub fn bundle_hash_from(path: &Path) -> Option<u64> {
+    let name = path.file_stem()?;
+    u64::from_str_radix(name.to_str()?, 16).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::*;
+    use crate::decompress::Stored;
+    use crate::duplicates::Owners;
+    use crate::file::Layout;
+    use crate::scoped_fs::ScopedFs;
+
+    #[test]
+    fn only_named() {
+        let lua = 0xa14e8dfa2cd117e2;
+        let texture = 0xcd4238c6a0c69e32;
+        let mut writer = BundleWriter::new(8);
+        writer.add_file(lua, 1, 0)
+            .variant(0, 0, b"unnamed", b"");
+        writer.add_file(texture, 2, 0)
+            .variant(0, 0, b"unnamed", b"");
+        let mut bundle = Vec::new();
+        writer.write(&mut bundle).unwrap();
+
+        let mut owners = Owners::new(Vec::new());
+        owners.add(0xb, [(lua, 1), (texture, 2)]);
+        let mut lua_filter = Filter::new();
+        lua_filter.include_ext(lua);
+
+        let layout = Layout::default();
+        let dictionary = HashMap::new();
+        let dictionary_short = HashMap::new();
+        let skipped = Mutex::new(HashMap::new());
+        let unknown_extensions = Mutex::new(HashMap::new());
+        for (owners, filter) in [
+            (None, None),
+            (Some(&owners), None),
+            (None, Some(&lua_filter)),
+            (Some(&owners), Some(&lua_filter)),
+        ] {
+            let options = ExtractOptions {
+                target: Path::new(""),
+                out: ScopedFs::new_null(Path::new("out")),
+                layout: &layout,
+                oodle: &Stored,
+                dictionary: &dictionary,
+                dictionary_short: &dictionary_short,
+                skip_extract: false,
+                skip_unknown: true,
+                skipped_unknown: &skipped,
+                unknown_extensions: &unknown_extensions,
+                owners,
+                conflicts: None,
+                as_blob: true,
+                chunk_pool: None,
+                prefetch: 0,
+            };
+            let mut rdr = Cursor::new(&bundle[..]);
+            let count = extract_bundle(
+                &mut Pool::new(),
+                &mut rdr,
+                &mut Vec::new(),
+                Some(0xb),
+                &Mutex::new(HashMap::new()),
+                &options,
+                filter,
+            ).unwrap();
+            // the unnamed lua file is extracted, the texture skipped
+            assert_eq!(1, count, "owners {}, filter {}", owners.is_some(), filter.is_some());
+        }
+        assert_eq!(Some(&2), skipped.lock().unwrap().get(&texture));
+    }
+}
@@ -1,32 +1,53 @@
-#![feature(lazy_cell)]
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsStr;
 use std::fs;
 use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
 use std::sync::Arc;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 use std::sync::Mutex;
 use std::thread;
 use std::time::Instant;
-use std::io;
-use std::io::Read;
-use std::io::Seek;
 use std::panic;
 use std::path::Path;
 use std::path::PathBuf;
 
-mod bundle;
-use bundle::BundleFd;
-mod file;
-use file::ExtractOptions;
-use file::Pool;
-mod hash;
-use hash::MurmurHash;
-mod oodle;
-mod read;
-use read::ChunkReader;
-mod scoped_fs;
-use scoped_fs::ScopedFs;
+use limn::bundle_hash_from;
+use limn::crack;
+use limn::dictionary;
+use limn::duplicates::Conflict;
+use limn::duplicates::Conflicts;
+use limn::duplicates::Owners;
+use limn::extract_bundle;
+use limn::harvest::Harvest;
+use limn::harvest::Match;
+use limn::harvest::Targets;
+use limn::hash;
+use limn::hash::extension_name;
+use limn::hashes::HashesBin;
+use limn::info;
+use limn::info::BundleInfo;
+use limn::oodle;
+use limn::read::ChunkReader;
+use limn::read::StreamReader;
+use limn::scoped_fs::ScopedFs;
+use limn::BundleFd;
+use limn::ChunkPool;
+use limn::Decompressor;
+use limn::Dictionary;
+use limn::MurmurHash;
+use limn::MurmurHash32;
+use limn::ExtractOptions;
+use limn::file::Layout;
+use limn::file::UnknownExtension;
+use limn::filter::Pattern;
+use limn::Filter;
+use limn::Pool;
 
 fn print_help() {
     println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
@@ -34,20 +55,79 @@ fn print_help() {
     println!();
     println!("limn extracts files from resource bundles used in Darktide.");
     println!();
-    println!("limn uses oo2core_9_win64.dll to decompress the bundle. If it fails to load");
-    println!("oo2core_9_win64.dll then copy it from the Darktide binaries folder next to limn.");
+    println!("limn uses {} to decompress the bundle. If it fails to load", oodle::LIBRARY_NAMES[0]);
+    println!("{} then copy it from the Darktide binaries folder next to limn", oodle::LIBRARY_NAMES[0]);
+    println!("or pass its path with --oodle or the LIMN_OODLE environment variable.");
     println!();
     println!("USAGE:");
-    println!("limn.exe [OPTIONS] <FILTER>");
+    println!("limn [OPTIONS] <FILTER>");
+    println!("limn info [--json] [--oodle <PATH>] [--dictionary <PATH>]... [--extensions <PATH>]... <BUNDLE>");
+    println!("limn verify [--oodle <PATH>] [BUNDLE|DIR]");
+    println!("limn harvest [--hashes <PATH>] [--dictionary <PATH>]... [-o <PATH>] [BUNDLE|DIR]");
+    println!("limn crack [-w [NAME=]<PATH>]... [--hashes <PATH>] [--targets <PATH>]... [--resume <PATH>]");
+    println!("           [-o <PATH>] <TEMPLATE>...");
+    println!("limn hash [--json|--csv] [STRING]...");
+    println!("limn unhash [--json|--csv] [--dictionary <PATH>]... [HASH]...");
+    println!("limn hashes export [--json|--csv] [--dictionary <PATH>]... [--extensions <PATH>]... <HASHES>");
+    println!("limn hashes merge -o <PATH> <HASHES>...");
+    println!("limn hashes diff [--json|--csv] [--dictionary <PATH>]... [--extensions <PATH>]... <OLD> <NEW>");
     println!();
     println!("ARGS:");
-    println!("    <FILTER>  Extract files with matching extension. Supports \"*\" as a wildcard.");
+    println!("    <FILTER>  Extract files with matching extension. Supports \"*\" as a wildcard");
+    println!("              and \"!<EXT>\" to extract all other extensions.");
     println!();
     println!("OPTIONS:");
+    println!("        --dictionary <PATH>   Load names from a .txt, .csv or .json dictionary.");
+    println!("                              Can be given multiple times.");
+    println!("        --extensions <PATH>   Load extra file extension names, one per line.");
+    println!("        --only-named          Skip files without a name in the dictionaries.");
+    println!("        --include-unnamed     Extract files without a name as their hash (default).");
     println!("        --dump-hashes         Dump file extension and name hashes.");
     println!("        --dump-raw            Extract files without converting contents.");
-    println!("    -i, --input               Bundle or directory of bundles to extract.");
-    println!("    -f, --filter <FILTER>     Only extract files with matching extension.");
+    println!("    -i, --input               Bundle or directory of bundles to extract. \"-\" reads stdin.");
+    println!("    -o, --out <DIR>           Directory to extract to. Defaults to \"out\".");
+    println!("        --layout <TEMPLATE>   Paths of extracted files with {{bundle}}, {{ext}}, {{name}},");
+    println!("                              {{name_hash}} and {{lang}}. Defaults to \"{{name}}.{{ext}}\".");
+    println!("    -f, --filter <FILTER>     Only extract files with matching extension, or all others");
+    println!("                              with \"!<EXT>\". Can be given multiple times.");
+    println!("        --include <GLOB>      Only extract files with a matching name like \"scripts/ui/**\"");
+    println!("                              or \"content/**/*.material\". Can be given multiple times.");
+    println!("        --exclude <GLOB>      Skip files with a matching name. Can be given multiple times.");
+    println!("        --include-regex <RE>  Same as --include with a regex (needs the regex feature).");
+    println!("        --exclude-regex <RE>  Same as --exclude with a regex (needs the regex feature).");
+    println!("        --names-from <PATH>   Only extract the files listed in PATH, one name or ext:name");
+    println!("                              pair per line, as names or hashes with 16 digits.");
+    println!("        --prefer <BUNDLE>     Extract files that are in several bundles from BUNDLE. Can be");
+    println!("                              given multiple times, otherwise the lowest bundle hash wins.");
+    println!("        --conflicts           List files with different contents in different bundles");
+    println!("                              instead of extracting.");
+    println!("        --oodle <PATH>        Path of the Oodle library to load.");
+    println!("        --paranoid            Verify checksums of compressed data.");
+    println!("        --mmap                Read bundles through memory maps (needs the mmap feature).");
+    println!("        --prefetch <N>        Decompress N chunks of a bundle in parallel.");
+    println!("                              Defaults to the number of threads for a single bundle");
+    println!("                              and for the last bundles of a directory.");
+    println!();
+    println!("SUBCOMMANDS:");
+    println!("    info    Print the header, chunks and entries of a bundle.");
+    println!("            --json prints the same as JSON.");
+    println!("    verify  Check every bundle without extracting and exit with 1 if any failed.");
+    println!("    harvest Collect names from lua files, materials and textures that match");
+    println!("            file names in the bundles or hashes.bin, or strings keys. The");
+    println!("            dictionaries and the new names are written to harvest.csv or -o.");
+    println!("    crack   Search names generated from templates like");
+    println!("            \"content/characters/{{word}}/{{word}}_{{num:2}}\" where {{word}} takes the words");
+    println!("            of the wordlist given with -w word=<PATH> and {{num:2}} the numbers 00 to 99.");
+    println!("            Names matching hashes.bin or the hashes in --targets that are not in a");
+    println!("            dictionary are appended to crack.csv or -o. With --resume <PATH> the");
+    println!("            position is saved to PATH and an interrupted search continues from it.");
+    println!("    hash    Print the hash and short hash of every string, or of every line of");
+    println!("            stdin if none are given.");
+    println!("    unhash  Look up hashes with 16 digits or short hashes with 8 digits in the");
+    println!("            dictionaries and file extensions, reading stdin if none are given.");
+    println!("    hashes  Read hashes.bin files written by --dump-hashes. export prints the");
+    println!("            pairs with resolved names, merge combines dumps of several game");
+    println!("            versions and diff lists the pairs added and removed in NEW.");
 }
 
 struct Args {
@@ -59,7 +139,39 @@ struct Args {
     // path to bundle OR directory of bundles
     target: PathBuf,
 
-    filter_ext: Option<u64>,
+    // directory files are extracted to and their paths in it
+    out: PathBuf,
+    layout: Layout,
+
+    // extensions and name patterns, None extracts everything
+    filter: Option<Filter>,
+
+    // explicit path of oodle library
+    oodle: Option<PathBuf>,
+
+    // check CRCs when decompressing
+    paranoid: bool,
+
+    // read bundles with crate::mmap::MappedBundle
+    mmap: bool,
+
+    // chunks decompressed in parallel within a bundle
+    prefetch: Option<usize>,
+
+    // dictionaries passed with --dictionary, loaded after the default ones
+    dictionaries: Vec<PathBuf>,
+
+    // skip files without a name in the dictionaries
+    only_named: bool,
+
+    // files with extra extension names
+    extensions: Vec<PathBuf>,
+
+    // bundles that duplicate files are extracted from first
+    prefer: Vec<u64>,
+
+    // hash duplicate files to find differing copies instead of extracting
+    conflicts: bool,
 
     darktide_path: Option<PathBuf>,
 }
@@ -71,7 +183,19 @@ fn parse_args() -> Result<Args, lexopt::Error> {
     let mut dump_raw = false;
 
     let mut target = None;
-    let mut filter_ext = None;
+    let mut out = PathBuf::from("./out");
+    let mut layout = Layout::default();
+    let mut filter = Filter::new();
+    let mut has_value = false;
+    let mut oodle = None;
+    let mut paranoid = false;
+    let mut mmap = false;
+    let mut prefetch = None;
+    let mut dictionaries = Vec::new();
+    let mut only_named = false;
+    let mut extensions = Vec::new();
+    let mut prefer = Vec::new();
+    let mut conflicts = false;
 
     let mut num_args = 0;
     let mut parser = lexopt::Parser::from_env();
@@ -81,33 +205,54 @@ fn parse_args() -> Result<Args, lexopt::Error> {
             Long("dump-hashes") => dump_hashes = true,
             Long("dump-raw") => dump_raw = true,
             Short('i') | Long("input") => target = Some(PathBuf::from(parser.value()?)),
+            Short('o') | Long("out") => out = PathBuf::from(parser.value()?),
+            Long("layout") => {
+                layout = Layout::parse(&parser.value()?.string()?).map_err(|e| e.to_string())?;
+            }
+            Long("oodle") => oodle = Some(PathBuf::from(parser.value()?)),
+            Long("paranoid") => paranoid = true,
+            Long("mmap") if cfg!(feature = "mmap") => mmap = true,
+            Long("mmap") => return Err("limn was built without the mmap feature".into()),
+            Long("prefetch") => prefetch = Some(parser.value()?.parse()?),
+            Long("dictionary") => dictionaries.push(PathBuf::from(parser.value()?)),
+            Long("only-named") => only_named = true,
+            Long("include-unnamed") => only_named = false,
+            Long("extensions") => extensions.push(PathBuf::from(parser.value()?)),
+            Long("prefer") => {
+                let bundle = PathBuf::from(parser.value()?);
+                prefer.push(bundle_hash_from(&bundle).ok_or("--prefer needs a bundle hash")?);
+            }
+            Long("conflicts") => conflicts = true,
             Long("help") => {
                 print_help();
                 std::process::exit(0);
             }
 
-            Short('f') | Long("filter") => {
-                let val = &*parser.value()?;
-                let s = val.to_str()
-                    .ok_or("extension filter must be valid UTF-8")?;
-                let h = match s {
-                    "*" => None,
-                    _ => Some(hash::murmur_hash64a(s.as_bytes(), 0)),
-                };
-                filter_ext = Some(h);
+            Short('f') | Long("filter") => add_ext_filter(&mut filter, &parser.value()?)?,
+            Long("include") => filter.include(Pattern::glob(pattern_str(&parser.value()?)?)),
+            Long("exclude") => filter.exclude(Pattern::glob(pattern_str(&parser.value()?)?)),
+            #[cfg(feature = "regex")]
+            Long("include-regex") => filter.include(
+                Pattern::regex(pattern_str(&parser.value()?)?).map_err(|e| e.to_string())?),
+            #[cfg(feature = "regex")]
+            Long("exclude-regex") => filter.exclude(
+                Pattern::regex(pattern_str(&parser.value()?)?).map_err(|e| e.to_string())?),
+            #[cfg(not(feature = "regex"))]
+            Long("include-regex") | Long("exclude-regex") => {
+                return Err("limn was built without the regex feature".into());
+            }
+            Long("names-from") => {
+                let path = PathBuf::from(parser.value()?);
+                fs::read_to_string(&path)
+                    .and_then(|data| filter.add_name_list(&data))
+                    .map_err(|e| format!("{}: {e}", path.display()))?;
             }
             Value(val) => {
-                if filter_ext.is_some() {
+                if has_value {
                     return Err(arg.unexpected());
                 }
-
-                let s = val.to_str()
-                    .ok_or("extension filter must be valid UTF-8")?;
-                let h = match s {
-                    "*" => None,
-                    _ => Some(hash::murmur_hash64a(s.as_bytes(), 0)),
-                };
-                filter_ext = Some(h);
+                has_value = true;
+                add_ext_filter(&mut filter, val)?;
             }
             _ => return Err(arg.unexpected()),
         }
@@ -118,189 +263,922 @@ fn parse_args() -> Result<Args, lexopt::Error> {
         std::process::exit(0);
     }
 
-    // hack to signal dupe/hash tracking
-    if dump_hashes && filter_ext.is_none() {
-        filter_ext = Some(Some(0));
-    }
-
-    let darktide_path = steam_find::get_steam_app(1361210).map(|app| app.path);
-    let target = target.unwrap_or_else(|| {
-        match &darktide_path {
-            Ok(path) => path.join("bundle"),
-            Err(e) => {
-                eprintln!("Darktide steam installation was not found:\n{e:?}");
-                std::process::exit(1);
-            }
-        }
-    });
+    let (target, darktide_path) = resolve_target(target);
 
     Ok(Args {
         dump_hashes,
         dump_raw,
 
         target,
-        filter_ext: filter_ext.flatten(),
-        darktide_path: darktide_path.ok(),
+        out,
+        layout,
+        filter: (!filter.is_empty()).then_some(filter),
+        oodle,
+        paranoid,
+        mmap,
+        prefetch,
+        dictionaries,
+        only_named,
+        extensions,
+        prefer,
+        conflicts,
+        darktide_path,
     })
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    match env::args_os().nth(1).as_ref().and_then(|cmd| cmd.to_str()) {
+        Some("info") => return info_main(),
+        Some("verify") => return verify_main(),
+        Some("harvest") => return harvest_main(),
+        Some("crack") => return crack_main(),
+        Some("hash") => return hash_main(),
+        Some("unhash") => return unhash_main(),
+        Some("hashes") => return hashes_main(),
+        _ => (),
+    }
+
     let Args {
         dump_hashes,
         dump_raw,
 
         target,
-        filter_ext,
+        out,
+        layout,
+        filter,
+        oodle,
+        paranoid,
+        mmap,
+        prefetch,
+        dictionaries,
+        only_named,
+        extensions,
+        prefer,
+        conflicts,
         darktide_path,
     } = parse_args()?;
 
-    let dictionary = fs::read_to_string("dictionary.txt");
-    let (dictionary, skip_unknown) = if let Ok(data) = dictionary.as_ref() {
-        let mut dict = HashMap::with_capacity(0x1000);
-        for key in data.lines() {
-            if !key.is_empty() {
-                dict.insert(MurmurHash::new(key), key);
-            }
-        }
-        (dict, true)
-    } else {
-        (HashMap::new(), false)
+    let bundles = match target == Path::new("-") {
+        true => None,
+        false => fs::read_dir(&target).ok().map(list_bundles),
     };
+    if conflicts && bundles.is_none() {
+        return Err("--conflicts needs a directory of bundles".into());
+    }
 
-    let oodle = match load_oodle("oo2core_9_win64.dll", &target, darktide_path.as_ref())
-        .or_else(|_| load_oodle("oo2core_8_win64.dll", &target, darktide_path.as_ref()))
-    {
-        Ok(oodle) => oodle,
-        Err(e) => {
-            eprintln!("oo2core_9_win64.dll could not be loaded");
-            eprintln!("copy the dll from the Darktide binaries folder next to limn");
-            eprintln!();
-            return Err(Box::new(e));
-        }
-    };
+    load_extensions(&extensions)?;
+
+    let dictionary = load_dictionary(&dictionaries);
+    if only_named && dictionary.is_empty() {
+        eprintln!("--only-named was given but no dictionary was loaded");
+    }
+
+    let decompressor = load_decompressor(oodle, &target, darktide_path.as_ref(), paranoid);
 
-    let out_fs = if dump_hashes {
-        ScopedFs::new_null(Path::new("./out"))
+    let out_fs = if dump_hashes || conflicts {
+        ScopedFs::new_null(&out)
     } else {
-        ScopedFs::new(Path::new("./out"))
+        ScopedFs::new(&out)
     };
 
-    let mut options = ExtractOptions {
+    // every index is read first so files in several bundles are always
+    // extracted from the same one
+    let owners = bundles.as_ref().map(|bundles| find_owners(bundles, prefer));
+    let copies = Mutex::new(Conflicts::new());
+
+    let options = ExtractOptions {
         target: &target,
         out: out_fs,
-        oodle: &oodle,
-        dictionary: &dictionary,
-        dictionary_short: &dictionary.iter().map(|(k, v)| (k.clone_short(), *v)).collect(),
-        skip_extract: dump_hashes,
-        skip_unknown,
+        layout: &layout,
+        oodle: &*decompressor,
+        dictionary: &dictionary.names(),
+        dictionary_short: &dictionary.short_names(),
+        skip_extract: dump_hashes || conflicts,
+        skip_unknown: only_named,
+        skipped_unknown: &Mutex::new(HashMap::new()),
+        unknown_extensions: &Mutex::new(HashMap::new()),
+        owners: owners.as_ref(),
+        conflicts: conflicts.then_some(&copies),
         as_blob: dump_raw,
+        chunk_pool: None,
+        // other threads are busy with other bundles until the last ones, see
+        // thread_work
+        prefetch: prefetch.unwrap_or(0),
+    };
+
+    // hack to signal dupe/hash tracking with an extension nothing has
+    let mut track_filter = Filter::new();
+    track_filter.include_ext(0);
+    let extract_filter = match &filter {
+        Some(filter) => Some(filter),
+        None if dump_hashes => Some(&track_filter),
+        None => None,
     };
 
     let duplicates = Mutex::new(HashMap::new());
     let start = Instant::now();
-    let num_files = if let Ok(read_dir) = fs::read_dir(&target) {
-        let mut bundles = Vec::new();
-        for fd in read_dir {
-            let fd = fd.as_ref().unwrap();
-            let meta = fd.metadata().unwrap();
-            if meta.is_file() {
-                let path = fd.path();
-                if path.extension().is_some() {
-                    continue;
+    let result = thread::scope(|s| {
+        // decompresses the chunks of every bundle that is prefetched
+        let chunk_pool = (prefetch.unwrap_or(2) > 1)
+            .then(|| ChunkPool::new(s, &*decompressor, num_threads()));
+        let mut options = ExtractOptions {
+            out: options.out.clone(),
+            chunk_pool: chunk_pool.as_ref(),
+            ..options
+        };
+        if target == Path::new("-") {
+            // bundle piped through stdin
+            options.target = Path::new(".");
+            options.prefetch = prefetch.unwrap_or_else(num_threads);
+            let rdr = StreamReader::new(io::stdin().lock());
+            match extract_bundle(
+                &mut Pool::new(),
+                rdr,
+                &mut Vec::new(),
+                None,
+                &duplicates,
+                &options,
+                extract_filter,
+            ) {
+                Ok(num_files) => Some((num_files, 0)),
+                Err(e) => {
+                    eprintln!("{e}");
+                    Some((0, 1))
                 }
+            }
+        } else if let Some(bundles) = &bundles {
+            let num_threads = num_threads();
 
-                if let Some(bundle_hash) = bundle_hash_from(&path) {
-                    bundles.push((path, bundle_hash));
+            let mut dupes = duplicates.lock().unwrap();
+            dupes.reserve(0x10000);
+            drop(dupes);
+            let result = batch_threads(
+                num_threads,
+                bundles,
+                &duplicates,
+                &options,
+                extract_filter,
+                mmap,
+            );
+            match (result, &owners) {
+                (Some((num_files, failed)), Some(owners)) if !failed.is_empty() && !options.skip_extract => {
+                    let num_failed = failed.len() as u32;
+                    extract_copies(
+                        bundles,
+                        owners,
+                        failed,
+                        &duplicates,
+                        &options,
+                        extract_filter,
+                        mmap,
+                    ).map(|(copied, failed)| (num_files + copied, num_failed + failed))
                 }
+                (result, _) => result.map(|(num_files, failed)| (num_files, failed.len() as u32)),
             }
-        }
+        } else if let Ok(bundle) = File::open(&target) {
+            options.target = target.parent().unwrap();
+            options.prefetch = prefetch.unwrap_or_else(num_threads);
 
-        let num_threads = thread::available_parallelism()
-            .map(|i| i.get())
-            .unwrap_or(0)
-            .saturating_sub(1)
-            .max(1);
-
-        let mut dupes = duplicates.lock().unwrap();
-        dupes.reserve(0x10000);
-        drop(dupes);
-        batch_threads(
-            num_threads,
-            &bundles,
-            &duplicates,
-            &options,
-            filter_ext,
-        )
-    } else if let Ok(bundle) = File::open(&target) {
-        options.target = target.parent().unwrap();
-
-        let bundle_hash = bundle_hash_from(&target);
-        let mut buf = vec![0; 0x80000];
-        let mut rdr = ChunkReader::new(&mut buf, bundle);
-        Some(extract_bundle(
-            &mut Pool::new(),
-            &mut rdr,
-            &mut Vec::new(),
-            bundle_hash,
-            &duplicates,
-            &options,
-            filter_ext,
-        ).unwrap())
-    } else {
-        panic!("PATH argument was invalid");
-    };
+            let bundle_hash = bundle_hash_from(&target);
+            let result = match mmap {
+                #[cfg(feature = "mmap")]
+                true => extract_mapped(
+                    &mut Pool::new(),
+                    &bundle,
+                    &mut Vec::new(),
+                    bundle_hash,
+                    &duplicates,
+                    &options,
+                    extract_filter,
+                ),
+                _ => {
+                    let mut buf = vec![0; 0x80000];
+                    let mut rdr = ChunkReader::new(&mut buf, bundle);
+                    extract_bundle(
+                        &mut Pool::new(),
+                        &mut rdr,
+                        &mut Vec::new(),
+                        bundle_hash,
+                        &duplicates,
+                        &options,
+                        extract_filter,
+                    )
+                }
+            };
+            match result {
+                Ok(num_files) => Some((num_files, 0)),
+                Err(e) => {
+                    eprintln!("{e}");
+                    Some((0, 1))
+                }
+            }
+        } else {
+            panic!("PATH argument was invalid");
+        }
+    });
 
     println!();
-    if let Some(num_files) = num_files {
+    if let Some((num_files, num_failed)) = result {
         let ms = start.elapsed().as_millis();
         println!("DONE");
         println!("took {}.{}s", ms / 1000, ms % 1000);
         if !options.skip_extract {
             println!("extracted {num_files} files");
         }
+        print_skipped(&options.skipped_unknown.lock().unwrap());
+        print_unknown_extensions(&options.unknown_extensions.lock().unwrap());
+        if let Some(filter) = &filter {
+            print_missing(&filter.missing(duplicates.lock().unwrap().keys()), options.dictionary);
+        }
+        if let (true, Some(owners)) = (conflicts, &owners) {
+            print_conflicts(&copies.lock().unwrap().conflicts(), owners, options.dictionary);
+        }
 
         if dump_hashes {
-            let mut dupes = duplicates.into_inner()
-                .unwrap()
-                .into_iter()
-                .map(|(hashes, _count)| hashes)
-                .collect::<Vec<_>>();
-            dupes.sort();
-            let mut dupes = &dupes[..];
-            if let Some(filter) = filter_ext.filter(|f| *f != 0) {
-                let start = dupes.partition_point(|(ext, _)| *ext < filter);
-                let end = dupes.partition_point(|(ext, _)| *ext <= filter);
-                dupes = &dupes[start..end];
-            }
-            //let mut out = String::with_capacity((dupes.len() + 2) * (16 + 1 + 16 + 1));
-            //out.push_str("name,extension\n");
-            //for (ext, name) in &dupes {
-            //    writeln!(&mut out, "{name:016x},{ext:016x}").unwrap();
-            //}
-            //fs::write("hashes.csv", &out)?;
-            let mut bin = Vec::with_capacity(dupes.len() * 16);
-            for (ext, name) in dupes {
-                bin.extend_from_slice(&ext.to_le_bytes());
-                bin.extend_from_slice(&name.to_le_bytes());
-            }
-            fs::write("hashes.bin", &bin)?;
-            println!("{} file extension and name hashes written to \"hashes.bin\"", dupes.len());
+            let mut hashes = HashesBin::new(duplicates.into_inner().unwrap().into_keys().collect());
+            if let Some(filter) = &filter {
+                hashes = HashesBin::new(hashes.pairs().iter()
+                    .copied()
+                    .filter(|&(ext, name)| filter.matches(ext, name, options.dictionary))
+                    .collect());
+            }
+            // `limn hashes export` converts it to CSV or JSON
+            hashes.write(File::create("hashes.bin")?)?;
+            println!("{} file extension and name hashes written to \"hashes.bin\"", hashes.len());
+        }
+
+        if num_failed > 0 {
+            eprintln!("{num_failed} bundles could not be read");
+            std::process::exit(1);
         }
     } else {
-        // TODO app exit code
         println!("did not finish due to errors");
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn info_main() -> Result<(), Box<dyn std::error::Error>> {
+    use lexopt::prelude::*;
+
+    let mut json = false;
+    let mut oodle = None;
+    let mut dictionaries = Vec::new();
+    let mut extensions = Vec::new();
+    let mut target = None;
+    let mut parser = lexopt::Parser::from_args(env::args_os().skip(2));
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Long("json") => json = true,
+            Long("oodle") => oodle = Some(PathBuf::from(parser.value()?)),
+            Long("dictionary") => dictionaries.push(PathBuf::from(parser.value()?)),
+            Long("extensions") => extensions.push(PathBuf::from(parser.value()?)),
+            Value(val) if target.is_none() => target = Some(PathBuf::from(val)),
+            _ => return Err(arg.unexpected().into()),
+        }
+    }
+    let (target, darktide_path) = resolve_target(Some(target.ok_or("missing bundle path")?));
+
+    load_extensions(&extensions)?;
+    let dictionary = load_dictionary(&dictionaries);
+    let decompressor = load_decompressor(oodle, &target, darktide_path.as_ref(), false);
+
+    let mut fd = File::open(&target)?;
+    let mut buf = vec![0; 0x80000];
+    let mut rdr = ChunkReader::new(&mut buf, &mut fd);
+    let mut bundle = BundleFd::new(bundle_hash_from(&target), &mut rdr)?;
+    let info = BundleInfo::read(&mut bundle, &*decompressor)?;
+
+    let stdout = io::stdout().lock();
+    if json {
+        info.write_json(stdout, &dictionary.names())?;
+    } else {
+        info.write_text(stdout, &dictionary.names())?;
+    }
+
+    Ok(())
+}
+
+fn verify_main() -> Result<(), Box<dyn std::error::Error>> {
+    use lexopt::prelude::*;
+
+    let mut oodle = None;
+    let mut target = None;
+    let mut parser = lexopt::Parser::from_args(env::args_os().skip(2));
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Long("oodle") => oodle = Some(PathBuf::from(parser.value()?)),
+            Value(val) if target.is_none() => target = Some(PathBuf::from(val)),
+            _ => return Err(arg.unexpected().into()),
+        }
+    }
+    let (target, darktide_path) = resolve_target(target);
+    let decompressor = load_decompressor(oodle, &target, darktide_path.as_ref(), true);
+
+    let bundles = list_target(&target);
+
+    let start = Instant::now();
+    let next = AtomicUsize::new(0);
+    let failed = Mutex::new(Vec::new());
+    thread::scope(|s| {
+        for _ in 0..num_threads().min(bundles.len()) {
+            s.spawn(|| {
+                let mut buf = vec![0; 0x80000];
+                let mut scratch = Vec::new();
+                while let Some((path, _)) = bundles.get(next.fetch_add(1, Ordering::AcqRel)) {
+                    let result = File::open(path)
+                        .map_err(|e| e.to_string())
+                        .and_then(|fd| {
+                            let mut rdr = ChunkReader::new(&mut buf, fd);
+                            BundleFd::new(bundle_hash_from(path), &mut rdr)
+                                .and_then(|mut bundle| bundle.verify(&*decompressor, &mut scratch))
+                                .map_err(|e| e.to_string())
+                        });
+                    if let Err(e) = result {
+                        failed.lock().unwrap().push((path, e));
+                    }
+                }
+            });
+        }
+    });
+
+    let mut failed = failed.into_inner().unwrap();
+    failed.sort();
+    for (path, e) in &failed {
+        println!("{}: {e}", path.display());
+    }
+
+    let ms = start.elapsed().as_millis();
+    println!("verified {} bundles in {}.{:03}s", bundles.len(), ms / 1000, ms % 1000);
+    if !failed.is_empty() {
+        println!("{} bundles failed", failed.len());
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn harvest_main() -> Result<(), Box<dyn std::error::Error>> {
+    use lexopt::prelude::*;
+
+    let mut oodle = None;
+    let mut target = None;
+    let mut hashes = None;
+    let mut dictionaries = Vec::new();
+    let mut out = PathBuf::from("harvest.csv");
+    let mut parser = lexopt::Parser::from_args(env::args_os().skip(2));
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Long("oodle") => oodle = Some(PathBuf::from(parser.value()?)),
+            Long("hashes") => hashes = Some(PathBuf::from(parser.value()?)),
+            Long("dictionary") => dictionaries.push(PathBuf::from(parser.value()?)),
+            Short('o') | Long("out") => out = PathBuf::from(parser.value()?),
+            Value(val) if target.is_none() => target = Some(PathBuf::from(val)),
+            _ => return Err(arg.unexpected().into()),
+        }
+    }
+    let (target, darktide_path) = resolve_target(target);
+    let dictionary = load_dictionary(&dictionaries);
+    let decompressor = load_decompressor(oodle, &target, darktide_path.as_ref(), false);
+
+    let mut harvest = Harvest::new();
+    harvest.targets = load_targets(hashes)?;
+
+    let bundles = list_target(&target);
+
+    let start = Instant::now();
+    let next = AtomicUsize::new(0);
+    let failed = Mutex::new(Vec::new());
+    thread::scope(|s| {
+        let threads = (0..num_threads().min(bundles.len())).map(|_| s.spawn(|| {
+            let mut harvest = Harvest::new();
+            let mut buf = vec![0; 0x80000];
+            let mut scratch = Vec::new();
+            let mut entry_buf = Vec::new();
+            while let Some((path, _)) = bundles.get(next.fetch_add(1, Ordering::AcqRel)) {
+                let result = File::open(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|fd| {
+                        let mut rdr = ChunkReader::new(&mut buf, fd);
+                        BundleFd::new(bundle_hash_from(path), &mut rdr)
+                            .and_then(|mut bundle| harvest.bundle(
+                                &mut bundle,
+                                &*decompressor,
+                                &mut scratch,
+                                &mut entry_buf,
+                            ))
+                            .map_err(|e| e.to_string())
+                    });
+                if let Err(e) = result {
+                    failed.lock().unwrap().push((path, e));
+                }
+            }
+            harvest
+        })).collect::<Vec<_>>();
+
+        for thread in threads {
+            harvest.merge(thread.join().unwrap());
+        }
+    });
+
+    let mut failed = failed.into_inner().unwrap();
+    failed.sort();
+    for (path, e) in &failed {
+        eprintln!("{}: {e}", path.display());
+    }
+
+    let new_names = harvest.new_names(&dictionary);
+    let sources = new_names.iter()
+        .map(|(name, m, provenance)| {
+            let file = match extension_name(provenance.ext) {
+                Some(ext) => format!("{:016x}.{ext}", provenance.name),
+                None => format!("{:016x}.{:016x}", provenance.name, provenance.ext),
+            };
+            let bundle = provenance.bundle.map(|b| format!("{b:016x}/")).unwrap_or_default();
+            let short = if *m == Match::Short { " (short hash)" } else { "" };
+            (*name, format!("{} in {bundle}{file}{short}", provenance.source.as_str()))
+        })
+        .collect::<Vec<_>>();
+
+    let mut rows = dictionary.iter()
+        .map(|(hash, name)| (hash, name, ""))
+        .chain(sources.iter().map(|(name, source)| (hash::murmur_hash64a(name.as_bytes(), 0), *name, source.as_str())))
+        .collect::<Vec<_>>();
+    rows.sort_by(|a, b| a.1.cmp(b.1));
+    dictionary::write_csv(BufWriter::new(File::create(&out)?), rows)?;
+
+    let ms = start.elapsed().as_millis();
+    let num_short = new_names.iter().filter(|(_, m, _)| *m == Match::Short).count();
+    println!("harvested {} bundles in {}.{:03}s", bundles.len(), ms / 1000, ms % 1000);
+    println!("{} candidates matched against {} hashes", harvest.num_candidates(), harvest.targets.len());
+    println!("{} new names, {num_short} of them by strings key", new_names.len());
+    println!("{} names written to \"{}\"", dictionary.len() + new_names.len(), out.display());
+    if !failed.is_empty() {
+        println!("{} bundles failed", failed.len());
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn crack_main() -> Result<(), Box<dyn std::error::Error>> {
+    use lexopt::prelude::*;
+
+    let mut templates = Vec::new();
+    let mut wordlists = HashMap::new();
+    let mut hashes = None;
+    let mut target_lists = Vec::new();
+    let mut dictionaries = Vec::new();
+    let mut out = PathBuf::from("crack.csv");
+    let mut resume = None;
+    let mut threads = None;
+    let mut parser = lexopt::Parser::from_args(env::args_os().skip(2));
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Short('t') | Long("template") => templates.push(parser.value()?.string()?),
+            Short('w') | Long("words") => {
+                let val = parser.value()?.string()?;
+                let (name, path) = val.split_once('=').unwrap_or(("word", &val));
+                let data = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+                // duplicate words would only repeat candidates
+                let mut seen = HashSet::new();
+                let words = data.lines()
+                    .map(str::trim)
+                    .filter(|word| !word.is_empty() && seen.insert(*word))
+                    .map(String::from)
+                    .collect::<Vec<_>>();
+                wordlists.insert(name.to_string(), words);
+            }
+            Long("hashes") => hashes = Some(PathBuf::from(parser.value()?)),
+            Long("targets") => target_lists.push(PathBuf::from(parser.value()?)),
+            Long("dictionary") => dictionaries.push(PathBuf::from(parser.value()?)),
+            Short('o') | Long("out") => out = PathBuf::from(parser.value()?),
+            Long("resume") => resume = Some(PathBuf::from(parser.value()?)),
+            Long("threads") => threads = Some(parser.value()?.parse()?),
+            Value(val) => templates.push(val.string()?),
+            _ => return Err(arg.unexpected().into()),
+        }
+    }
+    if templates.is_empty() {
+        return Err("missing template".into());
+    }
+    let templates = templates.iter()
+        .map(|t| crack::Template::parse(t, &wordlists).map(|parsed| (t, parsed)))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut targets = load_targets(hashes)?;
+    for path in &target_lists {
+        fs::read_to_string(path)
+            .and_then(|data| targets.read_hex_list(&data))
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+    targets.remove_known(&load_dictionary(&dictionaries));
+    if targets.is_empty() {
+        return Err("no unknown hashes to search for, pass hashes.bin with --hashes or a list with --targets".into());
+    }
+
+    // one line of "<position> <domain hash> <template>" per template searched
+    let mut positions = HashMap::new();
+    if let Some(resume) = &resume {
+        match fs::read_to_string(resume) {
+            Ok(state) => {
+                for line in state.lines() {
+                    let invalid = || format!("{}: invalid line {line:?}", resume.display());
+                    let (position, rest) = line.split_once(' ').ok_or_else(invalid)?;
+                    let (domain_hash, template) = rest.split_once(' ').ok_or_else(invalid)?;
+                    let position = position.parse::<u64>().map_err(|_| invalid())?;
+                    let domain_hash = u64::from_str_radix(domain_hash, 16).map_err(|_| invalid())?;
+                    positions.insert(template.to_string(), (position, domain_hash));
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(format!("{}: {e}", resume.display()).into()),
+        }
+    }
+
+    // names already in the output are not written again, such as the matches
+    // found after the last saved position of an interrupted search
+    let mut written = HashSet::new();
+    match fs::read_to_string(&out) {
+        Ok(data) => written.extend(data.lines()
+            .filter_map(|line| line.split_once(','))
+            .filter_map(|(hash, _)| u64::from_str_radix(hash, 16).ok())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+        Err(e) => return Err(format!("{}: {e}", out.display()).into()),
+    }
+    let mut out_fd = fs::OpenOptions::new().create(true).append(true).open(&out)?;
+    if out_fd.metadata()?.len() == 0 {
+        writeln!(out_fd, "hash,name,source")?;
+    }
+
+    let num_threads = threads.unwrap_or_else(num_threads);
+    let start = Instant::now();
+    let mut num_found = 0;
+    for (text, template) in &templates {
+        let domain_hash = template.domain_hash();
+        let position = match positions.get(text.as_str()) {
+            Some(&(_, saved)) if saved != domain_hash => {
+                return Err(format!("{text}: wordlists changed since the search was saved to \"{}\", \
+                    remove its line to start over", resume.as_ref().unwrap().display()).into());
+            }
+            Some(&(position, _)) => position,
+            None => 0,
+        };
+        if position >= template.len() {
+            println!("{text}: already searched");
+            continue;
+        }
+        println!("{text}: searching {} candidates from {position}", template.len() - position);
+
+        let mut saved = Instant::now();
+        crack::search(template, &targets, position, num_threads, |position, found| {
+            for found in found {
+                let hash = hash::murmur_hash64a(found.name.as_bytes(), 0);
+                if !written.insert(hash) {
+                    continue;
+                }
+                let source = match found.found {
+                    Match::Name => format!("crack {text}"),
+                    Match::Short => format!("crack {text} (short hash)"),
+                };
+                println!("{hash:016x} {}", found.name);
+                dictionary::write_csv_row(&mut out_fd, hash, &found.name, &source)?;
+                num_found += 1;
+            }
+
+            // matches are written before the position so none are lost
+            if saved.elapsed().as_secs() >= 1 || position == template.len() {
+                out_fd.flush()?;
+                positions.insert(text.to_string(), (position, domain_hash));
+                if let Some(resume) = &resume {
+                    save_positions(resume, &positions)?;
+                }
+                println!("{position}/{}", template.len());
+                saved = Instant::now();
+            }
+            Ok(())
+        })?;
+    }
+
+    let ms = start.elapsed().as_millis();
+    println!("found {num_found} names in {}.{:03}s, written to \"{}\"", ms / 1000, ms % 1000, out.display());
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Output {
+    Text,
+    Json,
+    Csv,
+}
+
+// lines of stdin are used when no values were given
+fn values_or_stdin(values: Vec<String>) -> io::Result<Vec<String>> {
+    if !values.is_empty() {
+        return Ok(values);
+    }
+    io::stdin()
+        .lines()
+        .filter(|line| line.as_ref().map(|line| !line.trim_end_matches('\r').is_empty()).unwrap_or(true))
+        .map(|line| line.map(|line| line.trim_end_matches('\r').to_string()))
+        .collect()
+}
+
+fn hash_main() -> Result<(), Box<dyn std::error::Error>> {
+    use lexopt::prelude::*;
+
+    let mut output = Output::Text;
+    let mut names = Vec::new();
+    let mut parser = lexopt::Parser::from_args(env::args_os().skip(2));
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Long("json") => output = Output::Json,
+            Long("csv") => output = Output::Csv,
+            Value(val) => names.push(val.string()?),
+            _ => return Err(arg.unexpected().into()),
+        }
+    }
+    let names = values_or_stdin(names)?;
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    match output {
+        // readable as a dictionary
+        Output::Csv => writeln!(out, "hash,name,short")?,
+        Output::Json => write!(out, "[")?,
+        Output::Text => (),
+    }
+    for (i, name) in names.iter().enumerate() {
+        let hash = MurmurHash::new(name);
+        let short = hash.clone_short();
+        match output {
+            Output::Text => writeln!(out, "{hash:016x} {short:08x} {name}")?,
+            Output::Csv => {
+                write!(out, "{hash:016x},")?;
+                dictionary::write_csv_field(&mut out, name)?;
+                writeln!(out, ",{short:08x}")?;
+            }
+            Output::Json => {
+                if i > 0 {
+                    write!(out, ",")?;
+                }
+                write!(out, "{{\"name\":")?;
+                info::write_json_str(&mut out, name)?;
+                write!(out, ",\"hash\":\"{hash:016x}\",\"short\":\"{short:08x}\"}}")?;
+            }
+        }
+    }
+    if output == Output::Json {
+        writeln!(out, "]")?;
+    }
+
+    Ok(())
+}
+
+fn unhash_main() -> Result<(), Box<dyn std::error::Error>> {
+    use lexopt::prelude::*;
+
+    let mut output = Output::Text;
+    let mut hashes = Vec::new();
+    let mut dictionaries = Vec::new();
+    let mut parser = lexopt::Parser::from_args(env::args_os().skip(2));
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Long("json") => output = Output::Json,
+            Long("csv") => output = Output::Csv,
+            Long("dictionary") => dictionaries.push(PathBuf::from(parser.value()?)),
+            Value(val) => hashes.push(val.string()?),
+            _ => return Err(arg.unexpected().into()),
+        }
+    }
+    let hashes = values_or_stdin(hashes)?;
+    let dictionary = load_dictionary(&dictionaries);
+    let short_names = dictionary.short_names();
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    match output {
+        Output::Csv => writeln!(out, "hash,name,kind")?,
+        Output::Json => write!(out, "[")?,
+        Output::Text => (),
+    }
+    for (i, hex) in hashes.iter().enumerate() {
+        let digits = hex.trim();
+        let digits = digits.strip_prefix("0x").unwrap_or(digits);
+        let (hex, found) = match digits.len() {
+            16 => {
+                let hash = u64::from_str_radix(digits, 16)
+                    .map_err(|_| format!("invalid hash {hex:?}"))?;
+                let found = match dictionary.get(hash) {
+                    Some(name) => Some((name, "name")),
+                    None => extension_name(hash).map(|ext| (ext, "extension")),
+                };
+                (format!("{hash:016x}"), found)
+            }
+            8 => {
+                let short = u32::from_str_radix(digits, 16)
+                    .map_err(|_| format!("invalid hash {hex:?}"))?;
+                let found = short_names.get(&MurmurHash32::from(short)).map(|name| (*name, "short"));
+                (format!("{short:08x}"), found)
+            }
+            _ => return Err(format!("invalid hash {hex:?}, expected 16 or 8 hex digits").into()),
+        };
+
+        match (output, found) {
+            (Output::Text, Some((name, _))) => writeln!(out, "{hex} {name}")?,
+            (Output::Text, None) => writeln!(out, "{hex} (unknown)")?,
+            (Output::Csv, found) => {
+                let (name, kind) = found.unwrap_or_default();
+                write!(out, "{hex},")?;
+                dictionary::write_csv_field(&mut out, name)?;
+                writeln!(out, ",{kind}")?;
+            }
+            (Output::Json, found) => {
+                if i > 0 {
+                    write!(out, ",")?;
+                }
+                write!(out, "{{\"hash\":\"{hex}\",\"name\":")?;
+                match found {
+                    Some((name, kind)) => {
+                        info::write_json_str(&mut out, name)?;
+                        write!(out, ",\"kind\":\"{kind}\"}}")?;
+                    }
+                    None => write!(out, "null}}")?,
+                }
+            }
+        }
+    }
+    if output == Output::Json {
+        writeln!(out, "]")?;
     }
 
     Ok(())
 }
 
+fn hashes_main() -> Result<(), Box<dyn std::error::Error>> {
+    use lexopt::prelude::*;
+
+    let mut args = env::args_os().skip(2);
+    let command = args.next().and_then(|command| command.into_string().ok());
+    let mut output = Output::Text;
+    let mut dictionaries = Vec::new();
+    let mut extensions = Vec::new();
+    let mut out = None;
+    let mut paths = Vec::new();
+    let mut parser = lexopt::Parser::from_args(args);
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Long("json") => output = Output::Json,
+            Long("csv") => output = Output::Csv,
+            Long("dictionary") => dictionaries.push(PathBuf::from(parser.value()?)),
+            Long("extensions") => extensions.push(PathBuf::from(parser.value()?)),
+            Short('o') | Long("out") => out = Some(PathBuf::from(parser.value()?)),
+            Value(val) => paths.push(PathBuf::from(val)),
+            _ => return Err(arg.unexpected().into()),
+        }
+    }
+    load_extensions(&extensions)?;
+    let read = |path: &PathBuf| HashesBin::read(path).map_err(|e| format!("{}: {e}", path.display()));
+
+    match (command.as_deref(), &paths[..]) {
+        (Some("export"), [path]) => {
+            let hashes = read(path)?;
+            let dictionary = load_dictionary(&dictionaries);
+            let out = BufWriter::new(io::stdout().lock());
+            write_pairs(out, output, &dictionary, &[("", hashes.pairs())])?;
+        }
+        (Some("merge"), [_, ..]) => {
+            let out = out.ok_or("missing output path, pass it with -o <PATH>")?;
+            let mut merged = HashesBin::default();
+            for path in &paths {
+                merged.merge(&read(path)?);
+            }
+            merged.write(File::create(&out)?)?;
+            println!("{} pairs from {} files written to \"{}\"", merged.len(), paths.len(), out.display());
+        }
+        (Some("diff"), [old, new]) => {
+            let diff = read(old)?.diff(&read(new)?);
+            let dictionary = load_dictionary(&dictionaries);
+            let out = BufWriter::new(io::stdout().lock());
+            write_pairs(out, output, &dictionary, &[("added", &diff.added), ("removed", &diff.removed)])?;
+            if output == Output::Text {
+                println!("{} added, {} removed", diff.added.len(), diff.removed.len());
+            }
+        }
+        (Some("export"), _) => return Err("expected a single hashes.bin".into()),
+        (Some("merge"), _) => return Err("expected hashes.bin files to merge".into()),
+        (Some("diff"), _) => return Err("expected the old and new hashes.bin".into()),
+        _ => return Err("expected export, merge or diff".into()),
+    }
+
+    Ok(())
+}
+
+// groups with an empty label are written as plain lists, others are marked
+// with their label
+fn write_pairs(
+    mut out: impl Write,
+    output: Output,
+    dictionary: &Dictionary,
+    groups: &[(&str, &[(u64, u64)])],
+) -> io::Result<()> {
+    let labeled = groups.iter().any(|(label, _)| !label.is_empty());
+    match output {
+        Output::Csv if labeled => writeln!(out, "change,ext_hash,ext,name_hash,name")?,
+        Output::Csv => writeln!(out, "ext_hash,ext,name_hash,name")?,
+        Output::Json if labeled => write!(out, "{{")?,
+        Output::Json => (),
+        Output::Text => (),
+    }
+
+    for (i, (label, pairs)) in groups.iter().enumerate() {
+        if output == Output::Json {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            if labeled {
+                write!(out, "\"{label}\":")?;
+            }
+            write!(out, "[")?;
+        }
+
+        for (j, &(ext, name)) in pairs.iter().enumerate() {
+            let ext_name = extension_name(ext);
+            let name_str = dictionary.get(name);
+            match output {
+                Output::Text => {
+                    match *label {
+                        "added" => write!(out, "+ ")?,
+                        "removed" => write!(out, "- ")?,
+                        _ => (),
+                    }
+                    match ext_name {
+                        Some(ext) => write!(out, "{ext} ")?,
+                        None => write!(out, "{ext:016x} ")?,
+                    }
+                    match name_str {
+                        Some(name) => writeln!(out, "{name}")?,
+                        None => writeln!(out, "{name:016x}")?,
+                    }
+                }
+                Output::Csv => {
+                    if labeled {
+                        write!(out, "{label},")?;
+                    }
+                    write!(out, "{ext:016x},{},{name:016x},", ext_name.unwrap_or_default())?;
+                    dictionary::write_csv_field(&mut out, name_str.unwrap_or_default())?;
+                    writeln!(out)?;
+                }
+                Output::Json => {
+                    if j > 0 {
+                        write!(out, ",")?;
+                    }
+                    write!(out, "{{\"ext_hash\":\"{ext:016x}\",")?;
+                    if let Some(ext) = ext_name {
+                        write!(out, "\"ext\":\"{ext}\",")?;
+                    }
+                    write!(out, "\"name_hash\":\"{name:016x}\"")?;
+                    if let Some(name) = name_str {
+                        write!(out, ",\"name\":")?;
+                        info::write_json_str(&mut out, name)?;
+                    }
+                    write!(out, "}}")?;
+                }
+            }
+        }
+
+        if output == Output::Json {
+            write!(out, "]")?;
+        }
+    }
+
+    match output {
+        Output::Json if labeled => writeln!(out, "}}"),
+        Output::Json => writeln!(out),
+        _ => out.flush(),
+    }
+}
+
+fn save_positions(path: &Path, positions: &HashMap<String, (u64, u64)>) -> io::Result<()> {
+    let mut state = positions.iter().collect::<Vec<_>>();
+    state.sort();
+    let mut data = String::new();
+    for (template, (position, domain_hash)) in state {
+        data.push_str(&format!("{position} {domain_hash:016x} {template}\n"));
+    }
+    // replace the old state at once so an interrupted write does not lose it
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(tmp, path)
+}
+
 fn batch_threads(
     num_threads: usize,
     bundles: &[(PathBuf, u64)],
     duplicates: &Mutex<HashMap<(u64, u64), u64>>,
     options: &ExtractOptions,
-    filter: Option<u64>,
-) -> Option<u32> {
+    filter: Option<&Filter>,
+    mmap: bool,
+) -> Option<(u32, Vec<u64>)> {
     let bundle_index = Arc::new(AtomicUsize::new(0));
     let thread_errors = Arc::new(Mutex::new(Vec::with_capacity(num_threads)));
 
@@ -331,13 +1209,15 @@ fn batch_threads(
         let mut threads = Vec::with_capacity(num_threads);
         for _ in 0..num_threads {
             threads.push(s.spawn(|| {
-                panic::catch_unwind(|| thread_work(
+                // threads are stopped on the first panic
+                panic::catch_unwind(panic::AssertUnwindSafe(|| thread_work(
                     &bundles,
                     &bundle_index,
                     &duplicates,
                     &options,
                     filter,
-                ))
+                    mmap,
+                )))
             }));
         }
 
@@ -372,10 +1252,13 @@ fn batch_threads(
 
         if threads.iter().all(|t| t.is_ok()) {
             let mut num_files = 0;
+            let mut failed = Vec::new();
             for thread in threads {
-                num_files += thread.unwrap();
+                let (files, mut thread_failed) = thread.unwrap();
+                num_files += files;
+                failed.append(&mut thread_failed);
             }
-            Some(num_files)
+            Some((num_files, failed))
         } else {
             let thread_errors = thread_errors.lock().unwrap();
             if thread_errors.is_empty() {
@@ -419,134 +1302,493 @@ fn thread_work(
     bundle_index: &AtomicUsize,
     duplicates: &Mutex<HashMap<(u64, u64), u64>>,
     options: &ExtractOptions,
-    filter: Option<u64>,
-) -> u32 {
+    filter: Option<&Filter>,
+    mmap: bool,
+) -> (u32, Vec<u64>) {
     let mut pool = Pool::new();
     let mut buffer_reader = vec![0_u8; 0x80000];
     let mut bundle_buf = Vec::new();
     let mut num_files = 0;
+    let mut failed = Vec::new();
 
-    while let Some((path, bundle_hash)) =
-        bundles.get(bundle_index.fetch_add(1, Ordering::AcqRel))
-    {
-        let bundle = File::open(&path).unwrap();
-        let mut rdr = ChunkReader::new(&mut buffer_reader, bundle);
-        num_files += extract_bundle(
-            &mut pool,
-            &mut rdr,
-            &mut bundle_buf,
-            Some(*bundle_hash),
-            &duplicates,
-            &options,
-            filter,
-        ).unwrap();
+    loop {
+        let index = bundle_index.fetch_add(1, Ordering::AcqRel);
+        let Some((path, bundle_hash)) = bundles.get(index) else {
+            break;
+        };
+        // once fewer bundles remain than threads the threads without a bundle
+        // decompress chunks for the others
+        let tail;
+        let options = match options.chunk_pool {
+            Some(pool) if options.prefetch == 0 && bundles.len() - index < pool.threads() => {
+                tail = ExtractOptions {
+                    out: options.out.clone(),
+                    prefetch: pool.threads(),
+                    ..*options
+                };
+                &tail
+            }
+            _ => options,
+        };
+
+        let bundle = match File::open(&path) {
+            Ok(fd) => fd,
+            Err(e) => {
+                eprintln!("{}: {e}", path.display());
+                failed.push(*bundle_hash);
+                continue;
+            }
+        };
+        let result = match mmap {
+            #[cfg(feature = "mmap")]
+            true => extract_mapped(
+                &mut pool,
+                &bundle,
+                &mut bundle_buf,
+                Some(*bundle_hash),
+                duplicates,
+                options,
+                filter,
+            ),
+            _ => {
+                let mut rdr = ChunkReader::new(&mut buffer_reader, bundle);
+                extract_bundle(
+                    &mut pool,
+                    &mut rdr,
+                    &mut bundle_buf,
+                    Some(*bundle_hash),
+                    &duplicates,
+                    &options,
+                    filter,
+                )
+            }
+        };
+        match result {
+            Ok(count) => num_files += count,
+            Err(e) => {
+                // corrupt bundles are reported and skipped
+                eprintln!("{e}");
+                failed.push(*bundle_hash);
+            }
+        }
     }
 
-    num_files
+    (num_files, failed)
 }
 
-fn extract_bundle(
-    pool: &mut Pool,
-    mut rdr: impl Read + Seek,
-    bundle_buf: &mut Vec<u8>,
-    bundle_hash: Option<u64>,
+// Extract the files owned by failed bundles from their next copy. Returns the
+// number of files extracted and of bundles that failed doing so.
+fn extract_copies(
+    bundles: &[(PathBuf, u64)],
+    owners: &Owners,
+    failed: Vec<u64>,
     duplicates: &Mutex<HashMap<(u64, u64), u64>>,
-    options: &ExtractOptions<'_>,
-    filter: Option<u64>,
-) -> io::Result<u32> {
-    bundle_buf.clear();
-    let mut bundle = BundleFd::new(bundle_hash, &mut rdr)?;
-    let targets = if let Some(filter_ext) = filter {
-        let mut targets = Vec::new();
-        let mut dupes = duplicates.lock().unwrap();
-        for file in bundle.index() {
-            let key = (file.ext, file.name);
-            let entry = dupes.entry(key).or_insert(0);
-            *entry += 1;
-
-            if *entry == 1 && file.ext == filter_ext {
-                if options.skip_unknown
-                    && !options.dictionary.contains_key(&MurmurHash::from(file.name))
-                {
-                    continue;
-                }
-                targets.push((file.ext, file.name));
+    options: &ExtractOptions,
+    filter: Option<&Filter>,
+    mmap: bool,
+) -> Option<(u32, u32)> {
+    let mut num_files = 0;
+    let mut num_failed = 0;
+    let mut retry = owners.reassign(&failed.into_iter().collect());
+    while !retry.is_empty() {
+        let owning = retry.bundles();
+        let copies = bundles.iter()
+            .filter(|(_, bundle_hash)| owning.contains(bundle_hash))
+            .cloned()
+            .collect::<Vec<_>>();
+        println!("extracting the files of bundles that could not be read from {} other bundles", copies.len());
+
+        // the copies were counted in the unknown extensions of the first pass
+        let unknown_extensions = Mutex::new(HashMap::new());
+        let options = ExtractOptions {
+            out: options.out.clone(),
+            owners: Some(&retry),
+            unknown_extensions: &unknown_extensions,
+            ..*options
+        };
+        let (copied, failed) = batch_threads(num_threads(), &copies, duplicates, &options, filter, mmap)?;
+        num_files += copied;
+        num_failed += failed.len() as u32;
+        retry = retry.reassign(&failed.into_iter().collect());
+    }
+    Some((num_files, num_failed))
+}
+
+// bundles are files without extension named by their hash
+fn list_bundles(read_dir: fs::ReadDir) -> Vec<(PathBuf, u64)> {
+    let mut bundles = Vec::new();
+    for fd in read_dir {
+        let fd = fd.as_ref().unwrap();
+        let meta = fd.metadata().unwrap();
+        if meta.is_file() {
+            let path = fd.path();
+            if path.extension().is_some() {
+                continue;
+            }
+
+            if let Some(bundle_hash) = bundle_hash_from(&path) {
+                bundles.push((path, bundle_hash));
             }
         }
-        drop(dupes);
+    }
+    bundles
+}
 
-        if targets.is_empty() {
-            return Ok(0);
-        } else {
-            Some(targets)
+// bundles in the target directory or the target as a single bundle
+fn list_target(target: &Path) -> Vec<(PathBuf, u64)> {
+    match fs::read_dir(target) {
+        Ok(read_dir) => list_bundles(read_dir),
+        Err(_) => vec![(target.to_path_buf(), bundle_hash_from(target).unwrap_or(0))],
+    }
+}
+
+// target given on the command line or the bundle directory of the Darktide
+// installation found through steam, and the installation path if found
+fn resolve_target(target: Option<PathBuf>) -> (PathBuf, Option<PathBuf>) {
+    let darktide_path = steam_find::get_steam_app(1361210).map(|app| app.path);
+    let target = match (target, &darktide_path) {
+        (Some(target), _) => target,
+        (None, Ok(path)) => path.join("bundle"),
+        (None, Err(e)) => {
+            eprintln!("Darktide steam installation was not found:\n{e:?}");
+            std::process::exit(1);
         }
-    } else {
-        None
     };
+    (target, darktide_path.ok())
+}
+
+// hashes to search names for from --hashes, or from hashes.bin of
+// --dump-hashes in the current directory if there is one
+fn load_targets(hashes: Option<PathBuf>) -> Result<Targets, String> {
+    let mut targets = Targets::default();
+    let path = hashes.clone().unwrap_or_else(|| PathBuf::from("hashes.bin"));
+    match fs::read(&path) {
+        Ok(data) => targets.read_hashes_bin(&data)
+            .map_err(|e| format!("{}: {e}", path.display()))?,
+        Err(e) if hashes.is_some() => return Err(format!("{}: {e}", path.display())),
+        Err(_) => (),
+    }
+    Ok(targets)
+}
+
+fn num_threads() -> usize {
+    thread::available_parallelism()
+        .map(|i| i.get())
+        .unwrap_or(0)
+        .saturating_sub(1)
+        .max(1)
+}
+
+// dictionaries next to limn, in the config directory and in the current
+// directory are loaded first, then the ones passed with --dictionary.
+// exits if an explicit dictionary cannot be loaded
+fn load_dictionary(explicit: &[PathBuf]) -> Dictionary {
+    let mut dirs = Vec::new();
+    if let Some(exe_dir) = env::current_exe().ok().as_ref().and_then(|p| p.parent()) {
+        dirs.push(exe_dir.to_path_buf());
+    }
+    if let Some(config_dir) = config_dir() {
+        dirs.push(config_dir);
+    }
+    dirs.push(PathBuf::from("."));
 
-    if options.skip_extract {
-        return Ok(targets.as_ref().map(|t| t.len() as u32).unwrap_or(0));
+    let mut paths = Vec::new();
+    for dir in &dirs {
+        for name in ["dictionary.txt", "dictionary.csv", "dictionary.json"] {
+            let path = dir.join(name);
+            if path.is_file() {
+                paths.push((path, false));
+            }
+        }
     }
+    paths.extend(explicit.iter().map(|path| (path.clone(), true)));
 
-    let mut targets = targets.as_ref().map(|t| &t[..]);
-    let mut count = 0;
-    let mut files = bundle.files(options.oodle, bundle_buf);
-    while let Ok(Some(file)) = files.next_file().map_err(|e| panic!("{:016x} - {}", bundle_hash.unwrap_or(0), e)) {
-        if options.skip_unknown
-            && file.ext != /*lua*/0xa14e8dfa2cd117e2
-            && !(filter == Some(file.ext) && file.ext == /*strings*/0x0d972bab10b40fd3)
-            && !options.dictionary.contains_key(&MurmurHash::from(file.name))
-        {
+    let mut loaded = Vec::new();
+    let mut dictionary = Dictionary::new();
+    for (path, is_explicit) in paths {
+        // the same file can be found through several directories
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if loaded.contains(&canonical) {
             continue;
         }
+        loaded.push(canonical);
 
-        if let Some(targets) = &mut targets {
-            let (ext, name) = targets.first().unwrap();
-            if *ext == file.ext && *name == file.name {
-                (_, *targets) = targets.split_at(1);
-            } else {
-                continue;
+        match dictionary.load(&path) {
+            Ok(report) => print_load_report(&path, &report),
+            Err(e) if is_explicit => {
+                eprintln!("dictionary {}: {e}", path.display());
+                std::process::exit(1);
             }
+            Err(e) => eprintln!("dictionary {} was skipped: {e}", path.display()),
+        }
+    }
+    dictionary
+}
+
+fn print_load_report(path: &Path, report: &limn::dictionary::LoadReport) {
+    const MAX_LISTED: usize = 10;
+
+    let path = path.display();
+    if report.duplicates > 0 {
+        eprintln!("dictionary {path}: {} duplicate names", report.duplicates);
+    }
+    for (hash, kept, skipped) in report.collisions.iter().take(MAX_LISTED) {
+        eprintln!("dictionary {path}: {skipped:?} collides with {kept:?} at {hash:016x}, keeping {kept:?}");
+    }
+    if report.collisions.len() > MAX_LISTED {
+        eprintln!("dictionary {path}: {} more collisions", report.collisions.len() - MAX_LISTED);
+    }
+    for (hash, name) in report.mismatches.iter().take(MAX_LISTED) {
+        eprintln!("dictionary {path}: {hash:016x} is not the hash of {name:?}, skipping it");
+    }
+    if report.mismatches.len() > MAX_LISTED {
+        eprintln!("dictionary {path}: {} more mismatched hashes", report.mismatches.len() - MAX_LISTED);
+    }
+}
+
+fn print_skipped(skipped: &HashMap<u64, u64>) {
+    if skipped.is_empty() {
+        return;
+    }
+
+    let mut skipped = skipped.iter().map(|(&ext, &count)| (count, ext)).collect::<Vec<_>>();
+    // most skipped first
+    skipped.sort_by(|a, b| b.cmp(a));
+    let total = skipped.iter().map(|(count, _)| count).sum::<u64>();
+    println!("skipped {total} files without a name:");
+    for (count, ext) in skipped {
+        match extension_name(ext) {
+            Some(ext) => println!("  {ext} {count}"),
+            None => println!("  {ext:016x} {count}"),
         }
+    }
+}
+
+fn print_unknown_extensions(unknown: &HashMap<u64, UnknownExtension>) {
+    if unknown.is_empty() {
+        return;
+    }
+
+    let mut unknown = unknown.iter().collect::<Vec<_>>();
+    // most common first
+    unknown.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(b.0)));
+    println!("found {} unknown file extensions:", unknown.len());
+    for (ext, found) in unknown {
+        let bundles = found.bundles.iter().map(|b| format!("{b:016x}")).collect::<Vec<_>>();
+        println!("  {ext:016x} {} files, in {}", found.count, bundles.join(" "));
+    }
+}
 
-        match file::extract(file, pool, options) {
-            Ok(_wrote) => count += 1,
-            Err(_e) => (),//eprintln!("{e}"),
+// entries of --names-from that are in no bundle
+fn print_missing(missing: &[(Option<u64>, u64)], dictionary: &HashMap<MurmurHash, &str>) {
+    const MAX_LISTED: usize = 20;
+
+    if missing.is_empty() {
+        return;
+    }
+
+    println!("{} listed files were not found:", missing.len());
+    for &(ext, name) in missing.iter().take(MAX_LISTED) {
+        match ext {
+            Some(ext) => println!("  {}:{}", ext_name(ext, dictionary), hash_name(name, dictionary)),
+            None => println!("  {}", hash_name(name, dictionary)),
         }
+    }
+    if missing.len() > MAX_LISTED {
+        println!("  and {} more", missing.len() - MAX_LISTED);
+    }
+}
 
-        if let Some(targets) = &targets {
-            if targets.is_empty() {
-                break;
+fn print_conflicts(
+    conflicts: &[Conflict],
+    owners: &Owners,
+    dictionary: &HashMap<MurmurHash, &str>,
+) {
+    if conflicts.is_empty() {
+        println!("no files differ between bundles");
+        return;
+    }
+
+    println!("{} files differ between bundles:", conflicts.len());
+    for conflict in conflicts {
+        let (ext, name) = (conflict.ext, conflict.name);
+        println!("  {}.{}", hash_name(name, dictionary), ext_name(ext, dictionary));
+        for &(bundle, content) in &conflict.copies {
+            let extracted = if owners.owner(ext, name) == Some(bundle) { " (extracted)" } else { "" };
+            println!("    {bundle:016x} content {content:016x}{extracted}");
+        }
+    }
+}
+
+// name from the dictionary or 16 hex digits
+fn hash_name(hash: u64, dictionary: &HashMap<MurmurHash, &str>) -> String {
+    match dictionary.get(&MurmurHash::from(hash)) {
+        Some(name) => name.to_string(),
+        None => format!("{hash:016x}"),
+    }
+}
+
+fn ext_name(ext: u64, dictionary: &HashMap<MurmurHash, &str>) -> String {
+    extension_name(ext).map(str::to_string).unwrap_or_else(|| hash_name(ext, dictionary))
+}
+
+// read the index of every bundle to pick the one each file is extracted from
+fn find_owners(bundles: &[(PathBuf, u64)], priority: Vec<u64>) -> Owners {
+    let next = AtomicUsize::new(0);
+    let indexes = thread::scope(|s| {
+        let threads = (0..num_threads().min(bundles.len())).map(|_| s.spawn(|| {
+            let mut indexes = Vec::new();
+            let mut buf = vec![0; 0x80000];
+            while let Some((path, bundle_hash)) = bundles.get(next.fetch_add(1, Ordering::AcqRel)) {
+                // bundles that cannot be read are reported when extracting
+                let Ok(fd) = File::open(path) else {
+                    continue;
+                };
+                let mut rdr = ChunkReader::new(&mut buf, fd);
+                let index = BundleFd::new(Some(*bundle_hash), &mut rdr).and_then(|mut bundle| {
+                    Ok(bundle.index()?.map(|file| (file.ext, file.name)).collect::<Vec<_>>())
+                });
+                if let Ok(files) = index {
+                    indexes.push((*bundle_hash, files));
+                }
             }
+            indexes
+        })).collect::<Vec<_>>();
+        threads.into_iter().flat_map(|t| t.join().unwrap()).collect::<Vec<_>>()
+    });
+
+    // the owners do not depend on the order the indexes are added in
+    let mut owners = Owners::new(priority);
+    for (bundle_hash, files) in indexes {
+        owners.add(bundle_hash, files);
+    }
+    owners
+}
+
+// "lua", "!texture" to exclude an extension or "*" for any
+fn add_ext_filter(filter: &mut Filter, value: &OsStr) -> Result<(), lexopt::Error> {
+    let s = value.to_str().ok_or("extension filter must be valid UTF-8")?;
+    match s.strip_prefix('!') {
+        _ if s == "*" => (),
+        Some(ext) => filter.exclude_ext(hash::murmur_hash64a(ext.as_bytes(), 0)),
+        None => filter.include_ext(hash::murmur_hash64a(s.as_bytes(), 0)),
+    }
+    Ok(())
+}
+
+fn pattern_str(value: &OsStr) -> Result<&str, lexopt::Error> {
+    Ok(value.to_str().ok_or("name pattern must be valid UTF-8")?)
+}
+
+// one extension name per line
+fn load_extensions(paths: &[PathBuf]) -> Result<(), String> {
+    for path in paths {
+        let data = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        for name in data.lines().map(str::trim).filter(|name| !name.is_empty()) {
+            hash::add_extension(name);
         }
     }
+    Ok(())
+}
+
+// %APPDATA%\limn on Windows, $XDG_CONFIG_HOME/limn or ~/.config/limn elsewhere
+fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+    base.map(|base| base.join("limn"))
+}
 
-    Ok(count)
+// --oodle falls back to LIMN_OODLE, exits if no decompressor is available
+fn load_decompressor(
+    oodle: Option<PathBuf>,
+    target: &Path,
+    darktide_path: Option<&PathBuf>,
+    paranoid: bool,
+) -> Box<dyn Decompressor> {
+    let oodle = oodle.or_else(|| env::var_os("LIMN_OODLE").map(PathBuf::from));
+    match load_oodle(oodle, target, darktide_path) {
+        Ok(mut oodle) => {
+            if paranoid {
+                oodle.set_decode_options(oodle::OodleDecodeOptions {
+                    check_crc: true,
+                    ..oodle.decode_options()
+                });
+            }
+            Box::new(oodle)
+        }
+        #[cfg(feature = "kraken")]
+        Err(_) => {
+            eprintln!("{} could not be loaded, using the built in decoder", oodle::LIBRARY_NAMES[0]);
+            Box::new(limn::Kraken)
+        }
+        #[cfg(not(feature = "kraken"))]
+        Err(e) => {
+            eprintln!("{} could not be loaded", oodle::LIBRARY_NAMES[0]);
+            eprintln!("copy it from the Darktide binaries folder next to limn or pass its path with --oodle");
+            eprintln!();
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
 }
 
-fn bundle_hash_from(path: &Path) -> Option<u64> {
-    let name = path.file_stem()?;
-    u64::from_str_radix(name.to_str()?, 16).ok()
+#[cfg(feature = "mmap")]
+fn extract_mapped(
+    pool: &mut Pool,
+    fd: &File,
+    bundle_buf: &mut Vec<u8>,
+    bundle_hash: Option<u64>,
+    duplicates: &Mutex<HashMap<(u64, u64), u64>>,
+    options: &ExtractOptions,
+    filter: Option<&Filter>,
+) -> limn::Result<u32> {
+    let map = limn::mmap::MappedBundle::map(fd)
+        .map_err(|e| limn::Error::from_io(bundle_hash, 0, e))?;
+    let mut rdr = std::io::Cursor::new(map.bytes());
+    let mut bundle = BundleFd::from_bytes(bundle_hash, &mut rdr)?;
+    limn::extract_bundle_fd(pool, &mut bundle, bundle_buf, duplicates, options, filter)
 }
 
 fn load_oodle(
-    name: &str,
+    explicit: Option<PathBuf>,
     path: &Path,
     darktide_path: Option<&PathBuf>,
-) -> Result<oodle::Oodle, io::Error> {
-    match oodle::Oodle::load(name) {
-        Ok(out) => Ok(out),
-        Err(e) => {
-            let oodle_path = format!("binaries/{name}");
-            if let Some(oodle) = path.parent().map(|p| p.join(&oodle_path))
-                .and_then(|p| oodle::Oodle::load(p).ok())
-                .or_else(|| darktide_path.map(|path| path.join(&oodle_path))
-                    .and_then(|p| oodle::Oodle::load(p).ok()))
-            {
-                Ok(oodle)
-            } else {
-                Err(e)
+) -> Result<oodle::Oodle, oodle::LoadError> {
+    if let Some(explicit) = explicit {
+        return oodle::Oodle::load_first([explicit]);
+    }
+
+    let mut dirs = Vec::new();
+    if let Some(exe_dir) = env::current_exe().ok().as_ref().and_then(|p| p.parent()) {
+        dirs.push(exe_dir.to_path_buf());
+    }
+    dirs.push(PathBuf::from("."));
+    if let Some(paths) = env::var_os(oodle::LIBRARY_PATH_VAR) {
+        dirs.extend(env::split_paths(&paths).filter(|p| !p.as_os_str().is_empty()));
+    }
+    if let Some(parent) = path.parent() {
+        dirs.push(parent.join("binaries"));
+    }
+    if let Some(darktide_path) = darktide_path {
+        dirs.push(darktide_path.join("binaries"));
+    }
+
+    let mut paths = Vec::new();
+    for name in oodle::LIBRARY_NAMES {
+        for dir in &dirs {
+            let path = dir.join(name);
+            if !paths.contains(&path) {
+                paths.push(path);
             }
         }
+        // fallback to search of system loader
+        paths.push(PathBuf::from(name));
     }
+    oodle::Oodle::load_first(paths)
 }
@@ -0,0 +1,27 @@
+//! Memory mapped bundles.
+use std::fs::File;
+use std::io;
+use memmap2::Mmap;
+
+/// Bundle file mapped into memory.
+///
+/// Open it with [`crate::BundleFd::from_bytes`] over a cursor of
+/// [`MappedBundle::bytes`].
+///
+/// The file must not be modified while it is mapped. Bundles are only written
+/// when the game is updated so this holds while extracting.
+pub struct MappedBundle {
+    map: Mmap,
+}
+
+impl MappedBundle {
+    pub fn map(fd: &File) -> io::Result<Self> {
+        // SAFETY: see type documentation
+        let map = unsafe { Mmap::map(fd)? };
+        Ok(Self { map })
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.map
+    }
+}
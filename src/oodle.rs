@@ -1,14 +1,18 @@
 #![allow(dead_code)]
+use std::fmt;
 use std::io;
 use std::path::Path;
+use std::path::PathBuf;
 use std::ptr;
 use libloading::Symbol;
 use libloading::Library;
 
+use crate::decompress::Decompressor;
+
 // https://github.com/gildor2/UEViewer/blob/c444911a6ad65bff5266f273dd5bdf7dd6fb506e/Unreal/UnCoreCompression.cpp#L272
 // https://github.com/gildor2/UEViewer/blob/c444911a6ad65bff5266f273dd5bdf7dd6fb506e/Unreal/UnCoreCompression.cpp#L205
 #[allow(non_camel_case_types)]
-type OodleLZ_Decompress = extern "C" fn(
+type OodleLZ_Decompress = unsafe extern "C" fn(
     arg1: *const u8,
     arg2: u64,
     arg3: *mut u8,
@@ -27,49 +31,203 @@ type OodleLZ_Decompress = extern "C" fn(
 
 // https://github.com/jamesbloom/ozip/blob/master/ozip.cpp
 #[allow(non_camel_case_types)]
-type OodleLZDecoder_MemorySizeNeeded = unsafe extern fn(i32, i64) -> u64;
+type OodleLZDecoder_MemorySizeNeeded = unsafe extern "C" fn(i32, i64) -> u64;
+
+// https://github.com/jamesbloom/ozip/blob/master/ozip.cpp
+#[allow(non_camel_case_types)]
+type OodleLZ_Compress = unsafe extern "C" fn(
+    compressor: i32,
+    raw_buf: *const u8,
+    raw_len: i64,
+    comp_buf: *mut u8,
+    level: i32,
+    options: *const ::std::os::raw::c_void,
+    dictionary_base: *const u8,
+    lrm: *const ::std::os::raw::c_void,
+    scratch_mem: *mut u8,
+    scratch_size: i64,
+) -> i64;
+
+#[allow(non_camel_case_types)]
+type OodleLZ_GetCompressedBufferSizeNeeded = unsafe extern "C" fn(compressor: i32, raw_size: i64) -> i64;
+
+/// Compressors of `OodleLZ_Compress`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum OodleCompressor {
+    Kraken = 8,
+    Mermaid = 9,
+    Leviathan = 13,
+}
+
+/// Compression levels of `OodleLZ_Compress`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum OodleLevel {
+    HyperFast4 = -4,
+    HyperFast3 = -3,
+    HyperFast2 = -2,
+    HyperFast1 = -1,
+    None = 0,
+    SuperFast = 1,
+    VeryFast = 2,
+    Fast = 3,
+    Normal = 4,
+    Optimal1 = 5,
+    Optimal2 = 6,
+    Optimal3 = 7,
+    Optimal4 = 8,
+    Optimal5 = 9,
+}
+
+/// File names of the Oodle library on this platform, newest version first.
+#[cfg(windows)]
+pub const LIBRARY_NAMES: &[&str] = &[
+    "oo2core_9_win64.dll",
+    "oo2core_8_win64.dll",
+];
+#[cfg(target_os = "macos")]
+pub const LIBRARY_NAMES: &[&str] = &[
+    "liboo2coremac64.2.9.dylib",
+    "liboo2coremac64.2.8.dylib",
+];
+#[cfg(all(unix, not(target_os = "macos")))]
+pub const LIBRARY_NAMES: &[&str] = &[
+    "liboo2corelinux64.so.9",
+    "liboo2corelinux64.so.8",
+];
+
+/// Environment variable with the search path for shared libraries.
+#[cfg(windows)]
+pub const LIBRARY_PATH_VAR: &str = "PATH";
+#[cfg(target_os = "macos")]
+pub const LIBRARY_PATH_VAR: &str = "DYLD_LIBRARY_PATH";
+#[cfg(all(unix, not(target_os = "macos")))]
+pub const LIBRARY_PATH_VAR: &str = "LD_LIBRARY_PATH";
+
+/// Error with every path that was tried by [`Oodle::load_first`].
+#[derive(Debug)]
+pub struct LoadError {
+    pub tried: Vec<(PathBuf, io::Error)>,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to load Oodle from:")?;
+        for (path, e) in &self.tried {
+            write!(f, "\n    {}: {e}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Arguments passed to `OodleLZ_Decompress`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OodleDecodeOptions {
+    /// Validate compressed data instead of trusting it.
+    pub fuzz_safe: bool,
+    /// Check CRCs of compressed blocks if the compressor stored them.
+    pub check_crc: bool,
+    pub verbosity: i32,
+    pub thread_phase: i32,
+}
+
+impl Default for OodleDecodeOptions {
+    fn default() -> Self {
+        Self {
+            fuzz_safe: true,
+            check_crc: false,
+            verbosity: 3,
+            thread_phase: 3,
+        }
+    }
+}
 
 pub struct Oodle {
-    lib: Library,
+    decompress: OodleLZ_Decompress,
+    // compression is optional for loading the library
+    compress: Option<(OodleLZ_Compress, OodleLZ_GetCompressedBufferSizeNeeded)>,
+    memory_size_needed: u64,
+    options: OodleDecodeOptions,
+    // function pointers are valid while the library is loaded
+    _lib: Library,
 }
 
 impl Oodle {
     fn load_(path: &Path) -> io::Result<Self> {
-        let lib = unsafe {
-            Library::new(path).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
-        };
-        Ok(Self {
-            lib,
-        })
+        unsafe {
+            let lib = Library::new(path).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            let decompress = *symbol::<OodleLZ_Decompress>(&lib, "OodleLZ_Decompress")?;
+            let msn = symbol::<OodleLZDecoder_MemorySizeNeeded>(&lib, "OodleLZDecoder_MemorySizeNeeded")?;
+            let memory_size_needed = msn(-1, -1);
+            let compress = symbol::<OodleLZ_Compress>(&lib, "OodleLZ_Compress")
+                .and_then(|c| Ok((*c, *symbol::<OodleLZ_GetCompressedBufferSizeNeeded>(
+                    &lib, "OodleLZ_GetCompressedBufferSizeNeeded")?)))
+                .ok();
+
+            Ok(Self {
+                decompress,
+                compress,
+                memory_size_needed,
+                options: OodleDecodeOptions::default(),
+                _lib: lib,
+            })
+        }
     }
 
     pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
         Self::load_(path.as_ref())
     }
 
-    pub fn memory_size_needed(&self) -> io::Result<u64> {
-        unsafe {
-            let msn: Symbol<OodleLZDecoder_MemorySizeNeeded> = self.lib.get(b"OodleLZDecoder_MemorySizeNeeded")
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-            Ok(msn(-1, -1))
+    /// Load the first of `paths` that is a working Oodle library.
+    ///
+    /// A path that is only a file name is searched for by the system loader.
+    pub fn load_first<I: IntoIterator<Item = PathBuf>>(paths: I) -> Result<Self, LoadError> {
+        let mut tried = Vec::new();
+        for path in paths {
+            match Self::load_(&path) {
+                Ok(oodle) => return Ok(oodle),
+                Err(e) => tried.push((path, e)),
+            }
         }
+        Err(LoadError {
+            tried,
+        })
+    }
+
+    pub fn decode_options(&self) -> OodleDecodeOptions {
+        self.options
+    }
+
+    pub fn set_decode_options(&mut self, options: OodleDecodeOptions) {
+        self.options = options;
+    }
+
+    pub fn memory_size_needed(&self) -> io::Result<u64> {
+        Ok(self.memory_size_needed)
     }
 
     pub fn decompress(&self, data: &[u8], out: &mut [u8], scratch: &mut [u8]) -> io::Result<u64> {
+        let OodleDecodeOptions {
+            fuzz_safe,
+            check_crc,
+            verbosity,
+            thread_phase,
+        } = self.options;
+        // SAFETY: every buffer is passed with its length, Oodle reads at most
+        // data.len() bytes and writes at most out.len() and scratch.len()
+        // bytes. The function pointer is valid while _lib is loaded.
         let ret = unsafe {
-            // TODO cache
-            let decompress: Symbol<OodleLZ_Decompress> = self.lib.get(b"OodleLZ_Decompress")
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-            decompress(
+            (self.decompress)(
                 data.as_ptr(), data.len() as u64,
                 out.as_mut_ptr(), out.len() as u64,
-                1/*true*/, 0/*false*/, 3,
+                fuzz_safe.into(), check_crc.into(), verbosity,
                 ptr::null_mut(), 0, ptr::null_mut(),
                 //ptr::null_mut(), ptr::null_mut(), 0,
                 ptr::null_mut(), scratch.as_mut_ptr(), scratch.len() as u64,
-                3)
+                thread_phase)
         };
 
         if ret != out.len() as u64 {
@@ -78,4 +236,162 @@ impl Oodle {
             Ok(ret)
         }
     }
+
+    fn compress_fns(&self) -> io::Result<(OodleLZ_Compress, OodleLZ_GetCompressedBufferSizeNeeded)> {
+        self.compress.ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported,
+            "Oodle library does not support compression"))
+    }
+
+    /// Size of the output buffer needed to compress `len` bytes.
+    pub fn compressed_size_needed(&self, compressor: OodleCompressor, len: usize) -> io::Result<usize> {
+        let (_, size_needed) = self.compress_fns()?;
+        // SAFETY: takes no pointers and the function pointer is valid while
+        // _lib is loaded.
+        let size = unsafe { size_needed(compressor as i32, len as i64) };
+        Ok(size as usize)
+    }
+
+    /// Compress `data` into `out` returning the compressed size.
+    ///
+    /// `out` must be at least [`Oodle::compressed_size_needed`] bytes.
+    pub fn compress(
+        &self,
+        compressor: OodleCompressor,
+        level: OodleLevel,
+        data: &[u8],
+        out: &mut [u8],
+    ) -> io::Result<usize> {
+        let (compress, _) = self.compress_fns()?;
+        if out.len() < self.compressed_size_needed(compressor, data.len())? {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                "output buffer is too small for compressed data"));
+        }
+
+        // SAFETY: Oodle reads data.len() bytes of data and writes at most
+        // compressed_size_needed() bytes to out, which was checked above. The
+        // function pointer is valid while _lib is loaded.
+        let ret = unsafe {
+            compress(
+                compressor as i32,
+                data.as_ptr(), data.len() as i64,
+                out.as_mut_ptr(),
+                level as i32,
+                ptr::null(), ptr::null(), ptr::null(),
+                ptr::null_mut(), 0)
+        };
+
+        if ret <= 0 {
+            Err(io::Error::new(io::ErrorKind::Other, "failed to compress data"))
+        } else {
+            Ok(ret as usize)
+        }
+    }
+}
+
+unsafe fn symbol<'a, T>(lib: &'a Library, name: &str) -> io::Result<Symbol<'a, T>> {
+    lib.get(name.as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::NotFound,
+        format!("missing symbol {name}: {e}")))
+}
+
+impl Decompressor for Oodle {
+    fn memory_size_needed(&self) -> io::Result<u64> {
+        Oodle::memory_size_needed(self)
+    }
+
+    fn decompress(&self, data: &[u8], out: &mut [u8], scratch: &mut [u8]) -> io::Result<u64> {
+        Oodle::decompress(self, data, out, scratch)
+    }
+}
+
+/// Compressible chunk of runs, random bytes and paths for tests that compress
+/// with Oodle.
+#[cfg(test)]
+pub(crate) fn sample() -> Vec<u8> {
+    let mut data = Vec::with_capacity(0x80000);
+    let mut x = 0x1234_5678_u32;
+    while data.len() < 0x80000 {
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        data.extend(std::iter::repeat_n(x as u8 % 16, (x % 24) as usize));
+        data.extend_from_slice(&x.to_le_bytes()[..(x >> 8) as usize % 4]);
+        data.extend_from_slice(b"content/ui/materials/");
+    }
+    data.truncate(0x80000);
+    data
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use super::*;
+
+    fn load() -> Oodle {
+        let paths = env::var_os("LIMN_OODLE")
+            .map(PathBuf::from)
+            .into_iter()
+            .chain(LIBRARY_NAMES.iter().map(PathBuf::from));
+        Oodle::load_first(paths).unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs the Oodle library"]
+    fn round_trip() {
+        let oodle = load();
+
+        let data = sample();
+        let mut scratch = vec![0; oodle.memory_size_needed().unwrap() as usize];
+        for compressor in [
+            OodleCompressor::Kraken,
+            OodleCompressor::Mermaid,
+            OodleCompressor::Leviathan,
+        ] {
+            for level in [OodleLevel::SuperFast, OodleLevel::Normal, OodleLevel::Optimal2] {
+                let mut compressed = vec![0; oodle.compressed_size_needed(compressor, data.len()).unwrap()];
+                let size = oodle.compress(compressor, level, &data, &mut compressed).unwrap();
+                assert!(size < data.len(), "{compressor:?} {level:?}");
+
+                let mut out = vec![0; data.len()];
+                oodle.decompress(&compressed[..size], &mut out, &mut scratch).unwrap();
+                assert!(out == data, "{compressor:?} {level:?}");
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs the Oodle library"]
+    fn compressed_bundle() {
+        use std::io::Read;
+        use crate::bundle::BundleFd;
+        use crate::writer::BundleWriter;
+
+        let oodle = load();
+
+        let data = sample();
+        let mut writer = BundleWriter::new(8);
+        writer.add_file(1, 2, 0)
+            .variant(0, 0, &data, &data[..100]);
+        let mut bundle = Vec::new();
+        writer.write_compressed(&mut bundle, &oodle, OodleCompressor::Kraken, OodleLevel::Fast).unwrap();
+        assert!(bundle.len() < data.len());
+
+        let mut rdr = std::io::Cursor::new(&bundle[..]);
+        let mut fd = BundleFd::new(None, &mut rdr).unwrap();
+        let mut scratch = Vec::new();
+        let mut files = fd.files(&oodle, &mut scratch).unwrap();
+        let mut out = Vec::new();
+        files.next_file().unwrap().unwrap().read_to_end(&mut out).unwrap();
+        assert!(out[..data.len()] == data[..]);
+        assert!(out[data.len()..] == data[..100]);
+    }
+
+    #[test]
+    #[ignore = "needs the Oodle library"]
+    fn small_output_buffer() {
+        let oodle = load();
+
+        let data = sample();
+        let mut compressed = vec![0; 16];
+        assert!(oodle.compress(OodleCompressor::Kraken, OodleLevel::Fast, &data, &mut compressed).is_err());
+    }
 }
@@ -0,0 +1,46 @@
+//! Text with `{name}` placeholders, the syntax of `limn crack` templates and
+//! of the `--layout` of extracted files.
+use std::io;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Token<'a> {
+    Literal(&'a str),
+    /// Text between the braces of a placeholder.
+    Placeholder(&'a str),
+}
+
+/// Split `template` into literal text and placeholders.
+pub fn tokenize(template: &str) -> io::Result<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            tokens.push(Token::Literal(&rest[..start]));
+        }
+        let end = rest[start..].find('}')
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+                format!("unclosed placeholder in {template:?}")))?;
+        tokens.push(Token::Placeholder(&rest[start + 1..start + end]));
+        rest = &rest[start + end + 1..];
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Literal(rest));
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tokenize() {
+        assert_eq!(vec![
+            Token::Placeholder("bundle"),
+            Token::Literal("/"),
+            Token::Placeholder("name:2"),
+            Token::Literal(".lua"),
+        ], super::tokenize("{bundle}/{name:2}.lua").unwrap());
+        assert!(super::tokenize("a/{name").is_err());
+    }
+}
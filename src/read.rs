@@ -25,7 +25,7 @@ impl<'a, R: Read + Seek> ChunkReader<'a, R> {
 
     fn next_chunk(&mut self) -> io::Result<()> {
         self.offset = 0;
-        self.len = self.inner.read(&mut self.buffer[..]).unwrap();
+        self.len = self.inner.read(&mut self.buffer[..])?;
         Ok(())
     }
 }
@@ -70,7 +70,12 @@ impl<'a, R: Read + Seek> Seek for ChunkReader<'a, R> {
                 let start = self.inner.seek(SeekFrom::Current(0))? - self.len as u64;
                 offset as i64 - start as i64
             }
-            SeekFrom::End(_offset) => unimplemented!(),
+            SeekFrom::End(offset) => {
+                self.offset = 0;
+                self.len = 0;
+                self.read_chunk = true;
+                return self.inner.seek(SeekFrom::End(offset));
+            }
         };
 
         if seek_to < 0 || seek_to > self.len as i64 {
@@ -87,3 +92,144 @@ impl<'a, R: Read + Seek> Seek for ChunkReader<'a, R> {
         }
     }
 }
+
+/// Reader for streams that can't seek such as stdin or a pipe.
+///
+/// Seeking forward skips bytes while seeking backward fails. Bundles parsed
+/// in order through [`crate::BundleFd::files`] only ever seek forward.
+pub struct StreamReader<R: Read> {
+    inner: R,
+    position: u64,
+}
+
+impl<R: Read> StreamReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            position: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for StreamReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read> Seek for StreamReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let seek_to = match pos {
+            SeekFrom::Start(offset) => offset as i128,
+            SeekFrom::Current(offset) => self.position as i128 + offset as i128,
+            SeekFrom::End(_) => return Err(io::Error::new(io::ErrorKind::Unsupported,
+                "cannot seek from end of stream")),
+        };
+
+        if seek_to < self.position as i128 {
+            return Err(io::Error::new(io::ErrorKind::Unsupported,
+                "cannot seek backward in stream"));
+        }
+
+        let skip = (seek_to - self.position as i128) as u64;
+        let skipped = io::copy(&mut (&mut self.inner).take(skip), &mut io::sink())?;
+        self.position += skipped;
+        if skipped < skip {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::sync::Mutex;
+    use super::*;
+    use crate::bundle::BundleFd;
+    use crate::decompress::Stored;
+    use crate::file::Layout;
+    use crate::scoped_fs::ScopedFs;
+    use crate::writer::BundleWriter;
+    use crate::ExtractOptions;
+    use crate::Filter;
+    use crate::Pool;
+
+    #[test]
+    fn stream_bundle() {
+        let mut writer = BundleWriter::new(8);
+        writer.add_file(1, 10, 0)
+            .variant(0, 0, &[3; 0x80000 + 10], b"");
+        writer.add_file(2, 20, 0)
+            .variant(0, 0, b"second", b"tail");
+        let mut bundle = Vec::new();
+        writer.write(&mut bundle).unwrap();
+
+        // slices are Read but not Seek
+        let mut rdr = StreamReader::new(&bundle[..]);
+        let mut fd = BundleFd::new(None, &mut rdr).unwrap();
+        assert_eq!(2, fd.index().unwrap().count());
+        let mut scratch = Vec::new();
+        let mut files = fd.files(&Stored, &mut scratch).unwrap();
+        // body of first file is skipped
+        files.next_file().unwrap().unwrap();
+        let mut data = Vec::new();
+        files.next_file().unwrap().unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(b"secondtail", &data[..]);
+        // the index was read past
+        assert!(matches!(fd.index(), Err(crate::Error::Io { offset: 268, .. })));
+
+        assert!(rdr.seek(SeekFrom::Start(0)).is_err());
+    }
+    #[test]
+    fn stream_filter() {
+        let mut writer = BundleWriter::new(8);
+        writer.add_file(1, 10, 0)
+            .variant(0, 0, &[3; 0x80000 + 10], b"");
+        writer.add_file(2, 20, 0)
+            .variant(0, 0, b"second", b"tail");
+        let mut bundle = Vec::new();
+        writer.write(&mut bundle).unwrap();
+
+        let mut filter = Filter::new();
+        filter.include_ext(2);
+        let layout = Layout::default();
+        let dictionary = HashMap::new();
+        let dictionary_short = HashMap::new();
+        // extracting and --dump-hashes
+        for skip_extract in [false, true] {
+            let options = ExtractOptions {
+                target: Path::new(""),
+                out: ScopedFs::new_null(Path::new("out")),
+                layout: &layout,
+                oodle: &Stored,
+                dictionary: &dictionary,
+                dictionary_short: &dictionary_short,
+                skip_extract,
+                skip_unknown: false,
+                skipped_unknown: &Mutex::new(HashMap::new()),
+                unknown_extensions: &Mutex::new(HashMap::new()),
+                owners: None,
+                conflicts: None,
+                as_blob: true,
+                chunk_pool: None,
+                prefetch: 0,
+            };
+            let duplicates = Mutex::new(HashMap::new());
+            let count = crate::extract_bundle(
+                &mut Pool::new(),
+                StreamReader::new(&bundle[..]),
+                &mut Vec::new(),
+                None,
+                &duplicates,
+                &options,
+                Some(&filter),
+            ).unwrap();
+            assert_eq!(1, count);
+            assert_eq!(2, duplicates.lock().unwrap().len());
+        }
+    }
+}
@@ -4,7 +4,8 @@ use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
 
-pub(crate) struct ScopedFs {
+#[derive(Clone)]
+pub struct ScopedFs {
     root: PathBuf,
     is_null: bool,
 }
@@ -24,12 +25,12 @@ impl ScopedFs {
         }
     }
 
-    pub(crate) fn new(root: &Path) -> Self {
+    pub fn new(root: &Path) -> Self {
         Self::new_(root, false)
     }
 
     #[allow(dead_code)]
-    pub(crate) fn new_null(root: &Path) -> Self {
+    pub fn new_null(root: &Path) -> Self {
         Self::new_(root, true)
     }
 
@@ -51,7 +52,7 @@ impl ScopedFs {
         Ok(out)
     }
 
-    pub(crate) fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+    pub fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
         if self.is_null {
             Ok(())
         } else {
@@ -60,7 +61,7 @@ impl ScopedFs {
         }
     }
 
-    pub(crate) fn create(&self, path: &Path) -> io::Result<impl io::Write> {
+    pub fn create(&self, path: &Path) -> io::Result<impl io::Write> {
         if self.is_null {
             Ok(ScopedFd(None))
         } else {
@@ -70,7 +71,7 @@ impl ScopedFs {
     }
 }
 
-pub(crate) struct ScopedFd(Option<fs::File>);
+pub struct ScopedFd(Option<fs::File>);
 
 impl io::Write for ScopedFd {
     #[inline]
@@ -99,7 +100,10 @@ mod test {
     #[test]
     #[should_panic]
     fn scope() {
-        let scope = ScopedFs(PathBuf::from("sandbox"));
+        let scope = ScopedFs {
+            root: PathBuf::from("sandbox"),
+            is_null: false,
+        };
         let _ = scope.create(Path::new("../target/test.bin"));
     }
 }
@@ -0,0 +1,209 @@
+//! Writer for Darktide bundles.
+//!
+//! Chunks are stored raw which `BundleFd` passes through without
+//! decompressing unless they are compressed with Oodle. The last chunk is
+//! padded with zeroes to the full chunk size.
+
+use std::borrow::Cow;
+use std::io;
+use std::io::Write;
+use byteorder::WriteBytesExt;
+use byteorder::LE;
+
+use crate::bundle::align_16;
+use crate::bundle::CHUNK_SIZE;
+use crate::oodle::Oodle;
+use crate::oodle::OodleCompressor;
+use crate::oodle::OodleLevel;
+
+pub struct BundleWriter {
+    version: u8,
+    reserved: [u8; 256],
+    files: Vec<FileWriter>,
+}
+
+impl BundleWriter {
+    /// Create writer for bundle `version` 7 or 8.
+    pub fn new(version: u8) -> Self {
+        assert!(version == 7 || version == 8, "unsupported bundle version {version}");
+
+        Self {
+            version,
+            reserved: [0; 256],
+            files: Vec::new(),
+        }
+    }
+
+    /// Set the reserved block after the file count.
+    pub fn reserved(&mut self, reserved: [u8; 256]) -> &mut Self {
+        self.reserved = reserved;
+        self
+    }
+
+    /// Add a file with `ext` and `name` hashes. `mode` is only written to the
+    /// bundle index.
+    pub fn add_file(&mut self, ext: u64, name: u64, mode: u32) -> &mut FileWriter {
+        self.files.push(FileWriter {
+            ext,
+            name,
+            mode,
+            num_variants: 0,
+            header: Vec::new(),
+            data: Vec::new(),
+        });
+        self.files.last_mut().unwrap()
+    }
+
+    /// Write the bundle to `out` returning the number of bytes written.
+    pub fn write(&self, out: impl Write) -> io::Result<u64> {
+        self.write_(out, None)
+    }
+
+    /// Write the bundle with chunks compressed by `oodle`.
+    ///
+    /// Chunks that do not get smaller are stored raw.
+    pub fn write_compressed(
+        &self,
+        out: impl Write,
+        oodle: &Oodle,
+        compressor: OodleCompressor,
+        level: OodleLevel,
+    ) -> io::Result<u64> {
+        self.write_(out, Some((oodle, compressor, level)))
+    }
+
+    fn write_(
+        &self,
+        mut out: impl Write,
+        compress: Option<(&Oodle, OodleCompressor, OodleLevel)>,
+    ) -> io::Result<u64> {
+        let mut data = Vec::new();
+        for file in &self.files {
+            data.write_u64::<LE>(file.ext)?;
+            data.write_u64::<LE>(file.name)?;
+            data.write_u32::<LE>(file.num_variants)?;
+            data.write_u32::<LE>(0)?;
+            data.extend_from_slice(&file.header);
+            data.extend_from_slice(&file.data);
+        }
+        let total_size = u32::try_from(data.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "bundle is too large"))?;
+        let num_chunks = data.len().div_ceil(CHUNK_SIZE);
+        data.resize(num_chunks * CHUNK_SIZE, 0);
+
+        let mut chunks = Vec::with_capacity(num_chunks);
+        let mut buffer = Vec::new();
+        for chunk in data.chunks_exact(CHUNK_SIZE) {
+            if let Some((oodle, compressor, level)) = compress {
+                buffer.resize(oodle.compressed_size_needed(compressor, CHUNK_SIZE)?, 0);
+                let size = oodle.compress(compressor, level, chunk, &mut buffer)?;
+                if size < CHUNK_SIZE {
+                    chunks.push(Cow::Owned(buffer[..size].to_vec()));
+                    continue;
+                }
+            }
+            chunks.push(Cow::Borrowed(chunk));
+        }
+
+        let mut header = Vec::with_capacity(12 + 256 + self.files.len() * 20 + 4 + num_chunks * 4);
+        header.extend_from_slice(&[
+            // bundle version
+            self.version, 0x00,
+
+            // unknown
+            0x00, 0xF0,
+            0x03, 0x00,
+            0x00, 0x00,
+        ]);
+        header.write_u32::<LE>(self.files.len() as u32)?;
+        header.extend_from_slice(&self.reserved);
+        for file in &self.files {
+            header.write_u64::<LE>(file.ext)?;
+            header.write_u64::<LE>(file.name)?;
+            header.write_u32::<LE>(file.mode)?;
+        }
+        header.write_u32::<LE>(num_chunks as u32)?;
+        for chunk in &chunks {
+            header.write_u32::<LE>(chunk.len() as u32)?;
+        }
+        let padding = align_16(header.len() as u64) as usize;
+        header.resize(header.len() + padding, 0);
+        header.write_u32::<LE>(total_size)?;
+        header.write_u32::<LE>(0)?;
+        out.write_all(&header)?;
+
+        let mut wrote = header.len() as u64;
+        for chunk in &chunks {
+            let mut prefix = [0; 20];
+            (&mut prefix[..4]).write_u32::<LE>(chunk.len() as u32)?;
+            let len = 4 + align_16(wrote + 4) as usize;
+            out.write_all(&prefix[..len])?;
+            out.write_all(chunk)?;
+            wrote += (len + chunk.len()) as u64;
+        }
+
+        Ok(wrote)
+    }
+}
+
+pub struct FileWriter {
+    ext: u64,
+    name: u64,
+    mode: u32,
+    num_variants: u32,
+    header: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl FileWriter {
+    /// Add a variant of the file. `tail` is written directly after `body`.
+    pub fn variant(&mut self, kind: u32, unknown1: u8, body: &[u8], tail: &[u8]) -> &mut Self {
+        self.num_variants += 1;
+        self.header.write_u32::<LE>(kind).unwrap();
+        self.header.write_u8(unknown1).unwrap();
+        self.header.write_u32::<LE>(body.len() as u32).unwrap();
+        self.header.write_u8(1).unwrap();
+        self.header.write_u32::<LE>(tail.len() as u32).unwrap();
+        self.data.extend_from_slice(body);
+        self.data.extend_from_slice(tail);
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bundle::BundleFd;
+    use crate::bundle::IndexEntry;
+
+    #[test]
+    fn header_and_index() {
+        let mut writer = BundleWriter::new(7);
+        writer.add_file(0xa14e8dfa2cd117e2, 1, 0)
+            .variant(0, 0, b"body", b"");
+        writer.add_file(0xcd4238c6a0c69e32, 2, 3)
+            .variant(0, 0, &[0; 0x90000], b"tail");
+        let mut bundle = Vec::new();
+        let wrote = writer.write(&mut bundle).unwrap();
+        assert_eq!(wrote, bundle.len() as u64);
+
+        let mut rdr = io::Cursor::new(&bundle[..]);
+        let mut fd = BundleFd::new(Some(0x10), &mut rdr).unwrap();
+        assert_eq!(2, fd.num_files);
+        assert_eq!(fd.index().unwrap().collect::<Vec<_>>(), [
+            IndexEntry { ext: 0xa14e8dfa2cd117e2, name: 1, mode: 0 },
+            IndexEntry { ext: 0xcd4238c6a0c69e32, name: 2, mode: 3 },
+        ]);
+
+        // two chunks with the last one padded
+        let table = 12 + 256 + 2 * 20;
+        assert_eq!(2, u32::from_le_bytes(bundle[table..table + 4].try_into().unwrap()));
+        assert_eq!(bundle.len() % 16, 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn unknown_version() {
+        BundleWriter::new(9);
+    }
+}